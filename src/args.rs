@@ -0,0 +1,56 @@
+use serde::{de::Error, Deserialize, Deserializer};
+
+/// Parses a fraction between 0 and 1, e.g. for the probabilities passed to `Rng::gen_bool`
+/// (which panics on anything else)
+pub fn parse_fraction(s: &str) -> Result<f64, String> {
+    let fraction = s
+        .parse::<f64>()
+        .map_err(|e| format!("invalid fraction '{}': {}", s, e))?;
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err(format!(
+            "fraction must be between 0 and 1, got {}",
+            fraction
+        ));
+    }
+    Ok(fraction)
+}
+
+/// Parses a number of seconds for a `Duration`, which panics on negative or non-finite ones
+pub fn parse_secs(s: &str) -> Result<f64, String> {
+    let secs = s
+        .parse::<f64>()
+        .map_err(|e| format!("invalid number of seconds '{}': {}", s, e))?;
+    check_secs(secs, false)
+}
+
+/// Like [`parse_secs`] but for a period, which can't be zero either
+pub fn parse_period(s: &str) -> Result<f64, String> {
+    let secs = s
+        .parse::<f64>()
+        .map_err(|e| format!("invalid number of seconds '{}': {}", s, e))?;
+    check_secs(secs, true)
+}
+
+/// [`parse_secs`] for options that can also come from a scenario file
+pub fn deserialize_secs<'de, D: Deserializer<'de>>(d: D) -> Result<Option<f64>, D::Error> {
+    Option::<f64>::deserialize(d)?
+        .map(|secs| check_secs(secs, false))
+        .transpose()
+        .map_err(D::Error::custom)
+}
+
+/// [`parse_period`] for options that can also come from a scenario file
+pub fn deserialize_period<'de, D: Deserializer<'de>>(d: D) -> Result<Option<f64>, D::Error> {
+    Option::<f64>::deserialize(d)?
+        .map(|secs| check_secs(secs, true))
+        .transpose()
+        .map_err(D::Error::custom)
+}
+
+fn check_secs(secs: f64, positive: bool) -> Result<f64, String> {
+    if !secs.is_finite() || secs < 0.0 || (positive && secs == 0.0) {
+        let least = if positive { "more than" } else { "at least" };
+        return Err(format!("seconds must be {} 0, got {}", least, secs));
+    }
+    Ok(secs)
+}
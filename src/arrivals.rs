@@ -0,0 +1,201 @@
+use std::{fmt, future::Future, str::FromStr, time::Duration};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+use tokio::time::Instant;
+
+/// When an open-loop workload issues its requests, parsed from the command line
+///
+/// * `constant:<rate>` issues `rate` requests per second, evenly spaced
+/// * `poisson:<rate>` issues `rate` requests per second on average, with exponentially
+///   distributed gaps like independent clients would
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Arrivals {
+    Constant(f64),
+    Poisson(f64),
+}
+
+impl Arrivals {
+    /// Target requests per second
+    pub fn rate(&self) -> f64 {
+        match self {
+            Arrivals::Constant(rate) | Arrivals::Poisson(rate) => *rate,
+        }
+    }
+
+    /// Time from one intended request start to the next
+    pub fn gap(&self, rng: &mut impl Rng) -> Duration {
+        match self {
+            Arrivals::Constant(rate) => Duration::from_secs_f64(1.0 / rate),
+            Arrivals::Poisson(rate) => {
+                let uniform: f64 = rng.gen_range(f64::EPSILON..1.0);
+                Duration::from_secs_f64(-uniform.ln() / rate)
+            }
+        }
+    }
+}
+
+impl FromStr for Arrivals {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, rate) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected <kind>:<rate>, got '{}'", s))?;
+        let rate = rate
+            .trim()
+            .parse::<f64>()
+            .map_err(|e| format!("invalid rate '{}': {}", rate, e))?;
+        if !(rate > 0.0 && rate.is_finite()) {
+            return Err(format!("rate must be positive, got {}", rate));
+        }
+        match kind {
+            "constant" => Ok(Arrivals::Constant(rate)),
+            "poisson" => Ok(Arrivals::Poisson(rate)),
+            _ => Err(format!(
+                "unknown arrival process '{}', expected constant or poisson",
+                kind
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Arrivals {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Arrivals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arrivals::Constant(rate) => write!(f, "constant:{}", rate),
+            Arrivals::Poisson(rate) => write!(f, "poisson:{}", rate),
+        }
+    }
+}
+
+/// How an open-loop run kept up with its schedule
+#[derive(Debug, Default)]
+pub struct OpenLoopStats {
+    /// From the first intended start until the last request finished
+    pub elapsed_secs: f64,
+    /// Most requests that were in flight at once
+    pub max_in_flight: usize,
+    /// Furthest behind schedule a request was issued, non-zero when the client itself
+    /// can't keep up with the target rate
+    pub max_issue_lag_secs: f64,
+}
+
+impl OpenLoopStats {
+    pub fn log(&self) {
+        log::info!(
+            "Open-loop: at most {} requests in flight, issued up to {:?} seconds late",
+            self.max_in_flight,
+            self.max_issue_lag_secs
+        );
+        if self.max_issue_lag_secs > 0.1 {
+            log::warn!(
+                "The client fell {:?} seconds behind the target rate, latencies include that delay",
+                self.max_issue_lag_secs
+            );
+        }
+    }
+}
+
+/// Starts each of `tasks` at the time `arrivals` says, however many are already in flight, and
+/// calls `on_complete` with each output.
+///
+/// The latency passed to `on_complete` is measured from when the request *should* have
+/// started, so time spent queued behind a slow store or an overloaded client counts against
+/// the store instead of silently lowering the request rate (coordinated omission).
+pub async fn run_open_loop<I, Fut, T>(
+    tasks: I,
+    arrivals: Arrivals,
+    mut on_complete: impl FnMut(f64, T),
+) -> OpenLoopStats
+where
+    I: IntoIterator<Item = Fut>,
+    Fut: Future<Output = T>,
+{
+    let mut rng = StdRng::from_entropy();
+    let mut tasks = tasks.into_iter();
+    let mut pending = tasks.next();
+    let mut in_flight = FuturesUnordered::new();
+    let mut stats = OpenLoopStats::default();
+
+    let start = Instant::now();
+    let mut next_start = start;
+    loop {
+        tokio::select! {
+            biased;
+            Some((latency, output)) = in_flight.next(), if !in_flight.is_empty() => {
+                on_complete(latency, output);
+            }
+            // tokio's timer has millisecond resolution, so requests may start up to a millisecond
+            // late, and that counts towards their latency
+            _ = tokio::time::sleep_until(next_start), if pending.is_some() => {
+                let fut = pending.take().unwrap();
+                let intended = next_start;
+                stats.max_issue_lag_secs = stats
+                    .max_issue_lag_secs
+                    .max(intended.elapsed().as_secs_f64());
+                in_flight.push(async move {
+                    let output = fut.await;
+                    (intended.elapsed().as_secs_f64(), output)
+                });
+                stats.max_in_flight = stats.max_in_flight.max(in_flight.len());
+                next_start += arrivals.gap(&mut rng);
+                pending = tasks.next();
+            }
+            else => break,
+        }
+    }
+    stats.elapsed_secs = start.elapsed().as_secs_f64();
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let cases = [
+            ("constant:100", Ok(Arrivals::Constant(100.0))),
+            ("poisson:2.5", Ok(Arrivals::Poisson(2.5))),
+            ("poisson: 3 ", Ok(Arrivals::Poisson(3.0))),
+            ("100", Err("expected <kind>:<rate>")),
+            ("constant:", Err("invalid rate")),
+            ("constant:fast", Err("invalid rate")),
+            ("constant:0", Err("rate must be positive")),
+            ("poisson:-5", Err("rate must be positive")),
+            ("poisson:inf", Err("rate must be positive")),
+            ("poisson:NaN", Err("rate must be positive")),
+            ("bursty:10", Err("unknown arrival process 'bursty'")),
+            ("Constant:10", Err("unknown arrival process 'Constant'")),
+        ];
+        for (s, expected) in cases {
+            match (s.parse::<Arrivals>(), expected) {
+                (Ok(arrivals), Ok(expected)) => {
+                    assert_eq!(arrivals, expected, "{}", s);
+                    assert_eq!(arrivals.to_string().parse::<Arrivals>(), Ok(arrivals));
+                }
+                (Err(e), Err(expected)) => assert!(e.contains(expected), "{}: {}", s, e),
+                (result, _) => panic!("{}: unexpected {:?}", s, result),
+            }
+        }
+    }
+
+    #[test]
+    fn constant_gaps_are_even() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let arrivals = Arrivals::Constant(4.0);
+        assert_eq!(arrivals.gap(&mut rng), Duration::from_millis(250));
+        assert_eq!(arrivals.gap(&mut rng), Duration::from_millis(250));
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::{Duration, SystemTime};
+
+use aws_config::BehaviorVersion;
+use aws_sdk_s3::Client;
+use clap::Parser;
+
+/// Lists and aborts stale S3 multipart uploads left behind by interrupted benchmark runs
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[arg(short, long)]
+    bucket: String,
+
+    #[arg(short, long)]
+    prefix: Option<String>,
+
+    /// Only abort uploads initiated at least this many seconds ago
+    #[arg(long)]
+    min_age_secs: Option<u64>,
+
+    /// List the stale uploads without aborting them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+async fn make_client() -> Client {
+    let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+    aws_sdk_s3::Client::new(&config)
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let args = Args::parse();
+
+    let min_age = Duration::from_secs(args.min_age_secs.unwrap_or(60 * 60));
+    let prefix = args.prefix.unwrap_or_default();
+
+    let client = make_client().await;
+    let now = SystemTime::now();
+
+    let mut key_marker: Option<String> = None;
+    let mut upload_id_marker: Option<String> = None;
+    let mut num_found = 0;
+    let mut num_aborted = 0;
+    loop {
+        let page = client
+            .list_multipart_uploads()
+            .bucket(&args.bucket)
+            .prefix(&prefix)
+            .set_key_marker(key_marker.take())
+            .set_upload_id_marker(upload_id_marker.take())
+            .send()
+            .await;
+        let page = match page {
+            Ok(page) => page,
+            Err(e) => {
+                eprintln!("Listing multipart uploads failed: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        for upload in page.uploads() {
+            let (Some(key), Some(upload_id)) = (upload.key(), upload.upload_id()) else {
+                continue;
+            };
+            let age = upload
+                .initiated()
+                .and_then(|initiated| SystemTime::try_from(*initiated).ok())
+                .and_then(|initiated| now.duration_since(initiated).ok())
+                .unwrap_or_default();
+            if age < min_age {
+                continue;
+            }
+            num_found += 1;
+            println!(
+                "Stale multipart upload key={} upload_id={} age={}s",
+                key,
+                upload_id,
+                age.as_secs()
+            );
+            if args.dry_run {
+                continue;
+            }
+            match client
+                .abort_multipart_upload()
+                .bucket(&args.bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .send()
+                .await
+            {
+                Ok(_) => num_aborted += 1,
+                Err(e) => log::error!("Failed to abort upload {} of {}: {}", upload_id, key, e),
+            }
+        }
+
+        if !page.is_truncated().unwrap_or(false) {
+            break;
+        }
+        key_marker = page.next_key_marker().map(str::to_string);
+        upload_id_marker = page.next_upload_id_marker().map(str::to_string);
+    }
+
+    println!(
+        "Found {} stale multipart uploads under '{}', aborted {}",
+        num_found, prefix, num_aborted
+    );
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use object_store::{ClientConfigKey, ClientOptions, RetryConfig};
+use serde::Deserialize;
+
+use crate::{args::parse_secs, results::RunResults};
+
+/// Retry and HTTP client options for stores that talk to a remote service.  Anything left unset
+/// keeps object_store's default.
+#[derive(clap::Args, Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientArgs {
+    /// Retry a failed request at most this many times, defaults to 10
+    #[arg(long)]
+    pub max_retries: Option<usize>,
+
+    /// Stop retrying this many seconds after a request was first sent, defaults to 180
+    #[arg(long, value_parser = parse_secs)]
+    #[serde(deserialize_with = "crate::args::deserialize_secs")]
+    pub retry_timeout: Option<f64>,
+
+    /// Seconds to back off before the first retry, defaults to 0.1
+    #[arg(long, value_parser = parse_secs)]
+    #[serde(deserialize_with = "crate::args::deserialize_secs")]
+    pub init_backoff: Option<f64>,
+
+    /// Never back off for longer than this many seconds, defaults to 15
+    #[arg(long, value_parser = parse_secs)]
+    #[serde(deserialize_with = "crate::args::deserialize_secs")]
+    pub max_backoff: Option<f64>,
+
+    /// Grow the backoff by up to this factor after each retry, defaults to 2
+    #[arg(long)]
+    pub backoff_base: Option<f64>,
+
+    /// Seconds from connecting until the response body has arrived before a request times
+    /// out, defaults to 30
+    #[arg(long, value_parser = parse_secs)]
+    #[serde(deserialize_with = "crate::args::deserialize_secs")]
+    pub request_timeout: Option<f64>,
+
+    /// Seconds to wait for a connection, defaults to 5
+    #[arg(long, value_parser = parse_secs)]
+    #[serde(deserialize_with = "crate::args::deserialize_secs")]
+    pub connect_timeout: Option<f64>,
+
+    /// Seconds to keep idle connections around for reuse
+    #[arg(long, value_parser = parse_secs)]
+    #[serde(deserialize_with = "crate::args::deserialize_secs")]
+    pub pool_idle_timeout: Option<f64>,
+
+    /// Keep at most this many idle connections per host
+    #[arg(long)]
+    pub pool_max_idle_per_host: Option<usize>,
+
+    /// Only use HTTP/2 connections, rather than only HTTP/1
+    #[arg(long)]
+    pub http2: bool,
+
+    /// Send requests through this proxy
+    #[arg(long)]
+    pub proxy_url: Option<String>,
+
+    /// Allow plain HTTP endpoints
+    #[arg(long)]
+    pub allow_http: bool,
+}
+
+/// The client options recorded with the results
+const RECORDED_KEYS: [ClientConfigKey; 8] = [
+    ClientConfigKey::Timeout,
+    ClientConfigKey::ConnectTimeout,
+    ClientConfigKey::PoolIdleTimeout,
+    ClientConfigKey::PoolMaxIdlePerHost,
+    ClientConfigKey::Http1Only,
+    ClientConfigKey::Http2Only,
+    ClientConfigKey::ProxyUrl,
+    ClientConfigKey::AllowHttp,
+];
+
+impl ClientArgs {
+    /// `base` with whichever retry options were given applied on top
+    pub fn retry_config(&self, base: RetryConfig) -> RetryConfig {
+        let mut retry = base;
+        if let Some(max_retries) = self.max_retries {
+            retry.max_retries = max_retries;
+        }
+        if let Some(retry_timeout) = self.retry_timeout {
+            retry.retry_timeout = Duration::from_secs_f64(retry_timeout);
+        }
+        if let Some(init_backoff) = self.init_backoff {
+            retry.backoff.init_backoff = Duration::from_secs_f64(init_backoff);
+        }
+        if let Some(max_backoff) = self.max_backoff {
+            retry.backoff.max_backoff = Duration::from_secs_f64(max_backoff);
+        }
+        if let Some(base) = self.backoff_base {
+            retry.backoff.base = base;
+        }
+        retry
+    }
+
+    pub fn client_options(&self) -> ClientOptions {
+        let mut options = ClientOptions::new().with_allow_http(self.allow_http);
+        if let Some(timeout) = self.request_timeout {
+            options = options.with_timeout(Duration::from_secs_f64(timeout));
+        }
+        if let Some(timeout) = self.connect_timeout {
+            options = options.with_connect_timeout(Duration::from_secs_f64(timeout));
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            options = options.with_pool_idle_timeout(Duration::from_secs_f64(timeout));
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            options = options.with_pool_max_idle_per_host(max);
+        }
+        if self.http2 {
+            options = options.with_http2_only();
+        }
+        if let Some(proxy_url) = self.proxy_url.clone() {
+            options = options.with_proxy_url(proxy_url);
+        }
+        options
+    }
+
+    /// Records the retry config the store was built with, and the client options, in the run's
+    /// summary
+    pub fn record(&self, retry: &RetryConfig, results: &mut RunResults) {
+        let options = self.client_options();
+        let mut client = serde_json::Map::new();
+        client.insert("max_retries".to_string(), retry.max_retries.into());
+        client.insert(
+            "retry_timeout_secs".to_string(),
+            retry.retry_timeout.as_secs_f64().into(),
+        );
+        client.insert(
+            "init_backoff_secs".to_string(),
+            retry.backoff.init_backoff.as_secs_f64().into(),
+        );
+        client.insert(
+            "max_backoff_secs".to_string(),
+            retry.backoff.max_backoff.as_secs_f64().into(),
+        );
+        client.insert("backoff_base".to_string(), retry.backoff.base.into());
+        for key in RECORDED_KEYS {
+            if let Some(value) = options.get_config_value(&key) {
+                client.insert(key.as_ref().to_string(), value.into());
+            }
+        }
+        results.summary.insert("client".to_string(), client.into());
+    }
+}
@@ -0,0 +1,451 @@
+use std::{sync::Arc, time::Instant};
+
+use clap::{Parser, ValueEnum};
+use object_store::{path::Path, ObjectStore, PutMode, PutOptions, PutPayload, UpdateVersion};
+use object_store_bench::{
+    errors::{ErrorArgs, ErrorClass, ErrorCounts},
+    namespace::RunNamespace,
+    results::{IterationResult, RunResults},
+    stats::LatencySummary,
+    store::StoreArgs,
+    timeline::TimelineArgs,
+    timing::TimingArgs,
+};
+
+/// Races concurrent writers to create or update the same manifest object with conditional puts
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    store: StoreArgs,
+
+    #[command(flatten)]
+    timeline: TimelineArgs,
+
+    #[arg(long, value_enum, default_value_t = CommitMode::Create)]
+    mode: CommitMode,
+
+    #[arg(long)]
+    num_writers: Option<u64>,
+
+    /// Number of manifests every writer races to create (create mode)
+    #[arg(long)]
+    num_rounds: Option<u64>,
+
+    /// Number of successful commits each writer makes (update mode)
+    #[arg(long)]
+    commits_per_writer: Option<u64>,
+
+    /// Give up on a commit after this many conflicting attempts (update mode)
+    #[arg(long)]
+    max_attempts: Option<u64>,
+
+    /// How many times to run the race, each against fresh manifests, defaults to 1
+    #[arg(long)]
+    num_iterations: Option<u32>,
+
+    #[command(flatten)]
+    timing: TimingArgs,
+
+    #[command(flatten)]
+    errors: ErrorArgs,
+
+    /// Reuse the namespace of an earlier run instead of creating a new one
+    #[arg(long)]
+    run_id: Option<String>,
+
+    /// Delete everything written under the run's namespace once the run finishes
+    #[arg(long)]
+    cleanup: bool,
+
+    /// Write machine readable results to this file
+    #[arg(long)]
+    results: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CommitMode {
+    /// Every writer tries to create each manifest with `PutMode::Create`, only one may win
+    Create,
+    /// Writers read-modify-write a counter in one manifest with etag based `PutMode::Update`.
+    /// Not every store supports this, e.g. LocalFileSystem doesn't.
+    Update,
+}
+
+#[derive(Default)]
+struct WriterStats {
+    commits: u64,
+    attempts: u64,
+    /// Time from the first attempt of a commit until it succeeded
+    commit_latencies: Vec<f64>,
+    /// Time taken by each individual conditional put, successful or not
+    attempt_latencies: Vec<f64>,
+    errors: ErrorCounts,
+    /// The errors other than losing the race
+    unexpected_errors: ErrorCounts,
+}
+
+impl WriterStats {
+    fn merge(&mut self, other: WriterStats) {
+        self.commits += other.commits;
+        self.attempts += other.attempts;
+        self.commit_latencies.extend(other.commit_latencies);
+        self.attempt_latencies.extend(other.attempt_latencies);
+        self.errors.merge(&other.errors);
+        self.unexpected_errors.merge(&other.unexpected_errors);
+    }
+}
+
+/// Fails if the store doesn't support the conditional put at all, there's nothing to measure
+async fn create_writer(
+    store: Arc<dyn ObjectStore>,
+    manifests: Vec<Path>,
+    writer: u64,
+) -> object_store::Result<WriterStats> {
+    let mut stats = WriterStats::default();
+    for manifest in manifests {
+        let opts = PutOptions::from(PutMode::Create);
+        let payload = PutPayload::from(format!("writer-{}", writer));
+        let start = Instant::now();
+        let result = store.put_opts(&manifest, payload, opts).await;
+        let latency = start.elapsed().as_secs_f64();
+        stats.attempts += 1;
+        stats.attempt_latencies.push(latency);
+        match result {
+            Ok(_) => {
+                stats.commits += 1;
+                stats.commit_latencies.push(latency);
+            }
+            Err(e) => {
+                match ErrorClass::of(&e) {
+                    ErrorClass::AlreadyExists => {}
+                    ErrorClass::NotSupported => return Err(e),
+                    _ => {
+                        log::warn!("Unexpected error creating {}: {}", manifest, e);
+                        stats.unexpected_errors.record(&e);
+                    }
+                }
+                stats.errors.record(&e);
+            }
+        }
+    }
+    Ok(stats)
+}
+
+/// Reads the manifest's counter and the version to update it from
+async fn read_counter(
+    store: &dyn ObjectStore,
+    manifest: &Path,
+) -> object_store::Result<(UpdateVersion, u64)> {
+    let current = store.get(manifest).await?;
+    let version = UpdateVersion {
+        e_tag: current.meta.e_tag.clone(),
+        version: current.meta.version.clone(),
+    };
+    let bytes = current.bytes().await?;
+    let counter = std::str::from_utf8(&bytes)
+        .map_err(|e| e.to_string())
+        .and_then(|counter| counter.parse::<u64>().map_err(|e| e.to_string()))
+        .map_err(|e| object_store::Error::Generic {
+            store: "commit_contention",
+            source: format!("invalid manifest counter: {}", e).into(),
+        })?;
+    Ok((version, counter))
+}
+
+/// Fails if the store doesn't support the conditional put at all, like [`create_writer`]
+async fn update_writer(
+    store: Arc<dyn ObjectStore>,
+    manifest: Path,
+    num_commits: u64,
+    max_attempts: u64,
+) -> object_store::Result<WriterStats> {
+    let mut stats = WriterStats::default();
+    for _ in 0..num_commits {
+        let commit_start = Instant::now();
+        for _ in 0..max_attempts {
+            // A failed read uses up an attempt, like a conflicting put
+            let (version, counter) = match read_counter(store.as_ref(), &manifest).await {
+                Ok(current) => current,
+                Err(e) => {
+                    log::warn!("Error reading {}: {}", manifest, e);
+                    stats.errors.record(&e);
+                    stats.unexpected_errors.record(&e);
+                    continue;
+                }
+            };
+
+            let opts = PutOptions::from(PutMode::Update(version));
+            let start = Instant::now();
+            let result = store
+                .put_opts(&manifest, PutPayload::from((counter + 1).to_string()), opts)
+                .await;
+            stats.attempts += 1;
+            stats.attempt_latencies.push(start.elapsed().as_secs_f64());
+            match result {
+                Ok(_) => {
+                    stats.commits += 1;
+                    stats
+                        .commit_latencies
+                        .push(commit_start.elapsed().as_secs_f64());
+                    break;
+                }
+                Err(e) => {
+                    match ErrorClass::of(&e) {
+                        ErrorClass::Precondition => {}
+                        ErrorClass::NotSupported => return Err(e),
+                        _ => {
+                            log::warn!("Unexpected error updating {}: {}", manifest, e);
+                            stats.unexpected_errors.record(&e);
+                        }
+                    }
+                    stats.errors.record(&e);
+                }
+            }
+        }
+    }
+    Ok(stats)
+}
+
+/// Runs every writer against `manifest` (or the manifests under it, in create mode) and
+/// returns their merged stats along with how many writers failed, or the error of a writer that
+/// found the store doesn't support the conditional put
+async fn race(
+    store: &Arc<dyn ObjectStore>,
+    manifest: &Path,
+    mode: CommitMode,
+    num_writers: u64,
+    num_rounds: u64,
+    commits_per_writer: u64,
+    max_attempts: u64,
+) -> object_store::Result<(WriterStats, u64)> {
+    let writers = match mode {
+        CommitMode::Create => {
+            let manifests = (0..num_rounds)
+                .map(|round| manifest.child(round.to_string()))
+                .collect::<Vec<_>>();
+            (0..num_writers)
+                .map(|writer| tokio::spawn(create_writer(store.clone(), manifests.clone(), writer)))
+                .collect::<Vec<_>>()
+        }
+        CommitMode::Update => (0..num_writers)
+            .map(|_| {
+                tokio::spawn(update_writer(
+                    store.clone(),
+                    manifest.clone(),
+                    commits_per_writer,
+                    max_attempts,
+                ))
+            })
+            .collect::<Vec<_>>(),
+    };
+
+    let mut stats = WriterStats::default();
+    let mut failed_writers = 0;
+    let mut not_supported = None;
+    for writer in writers {
+        match writer.await {
+            Ok(Ok(writer_stats)) => stats.merge(writer_stats),
+            Ok(Err(e)) => not_supported = Some(e),
+            Err(e) => {
+                log::error!("Writer failed: {}", e);
+                failed_writers += 1;
+            }
+        }
+    }
+    match not_supported {
+        Some(e) => Err(e),
+        None => Ok((stats, failed_writers)),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    object_store_bench::retries::init_logging();
+
+    let args = Args::parse();
+
+    let num_writers = args.num_writers.unwrap_or(8);
+    let num_rounds = args.num_rounds.unwrap_or(100);
+    let commits_per_writer = args.commits_per_writer.unwrap_or(20);
+    let max_attempts = args.max_attempts.unwrap_or(1000);
+    let num_iterations = args.num_iterations.unwrap_or(1);
+
+    let (store, base_path) = args.store.build();
+    let timeline = args.timeline.start();
+    let store = timeline.wrap(store);
+    let namespace = RunNamespace::new(&base_path, args.run_id);
+    println!("Run id: {}", namespace.run_id);
+
+    let mut results = RunResults::new(
+        "commit_contention",
+        &namespace.run_id,
+        namespace.prefix.as_ref(),
+    );
+    args.store.record(&mut results);
+    let (mut total_failed_writers, mut non_atomic_iterations) = (0, 0);
+    let mut stopped_early = false;
+    let mut schedule = args.timing.schedule(num_iterations);
+    let mut iteration_idx = 0;
+    while schedule.next_iteration() {
+        let manifest = namespace.path(&format!("manifest/{}", iteration_idx));
+        iteration_idx += 1;
+        if matches!(args.mode, CommitMode::Update) {
+            if let Err(e) = store.put(&manifest, PutPayload::from_static(b"0")).await {
+                eprintln!("Writing {} failed: {}", manifest, e);
+                std::process::exit(1);
+            }
+        }
+
+        let total_start = Instant::now();
+        let raced = race(
+            &store,
+            &manifest,
+            args.mode,
+            num_writers,
+            num_rounds,
+            commits_per_writer,
+            max_attempts,
+        )
+        .await;
+        let (mut stats, failed_writers) = match raced {
+            Ok(raced) => raced,
+            Err(e) => {
+                eprintln!(
+                    "This store does not support the conditional puts of {:?} mode: {}",
+                    args.mode, e
+                );
+                std::process::exit(1);
+            }
+        };
+        let elapsed = total_start.elapsed().as_secs_f64();
+
+        // Check that the store really did serialize the writers
+        let expected_commits = match args.mode {
+            CommitMode::Create => num_rounds,
+            CommitMode::Update => match read_counter(store.as_ref(), &manifest).await {
+                Ok((_, counter)) => {
+                    println!("Final manifest counter: {}", counter);
+                    counter
+                }
+                Err(e) => {
+                    eprintln!("Couldn't read the final manifest counter: {}", e);
+                    stats.commits
+                }
+            },
+        };
+        if failed_writers > 0 {
+            println!(
+                "WARNING: {} of {} writers failed, their commits are missing from the totals",
+                failed_writers, num_writers
+            );
+            total_failed_writers += failed_writers;
+        } else if stats.commits != expected_commits {
+            println!(
+                "WARNING: {} commits succeeded but the store shows {}, conditional puts are not atomic",
+                stats.commits, expected_commits
+            );
+            non_atomic_iterations += 1;
+        }
+
+        let conflicts = stats.errors.get(ErrorClass::AlreadyExists)
+            + stats.errors.get(ErrorClass::Precondition);
+        let conflict_rate = conflicts as f64 / stats.attempts as f64;
+        let commit_latency = LatencySummary::from_samples(&mut stats.commit_latencies);
+        let attempt_latency = LatencySummary::from_samples(&mut stats.attempt_latencies);
+        println!(
+            "{:?} with {} writers took {:?} seconds: {} commits ({} commits/s), {} attempts, {} conflicts ({:.1}%), {}",
+            args.mode,
+            num_writers,
+            elapsed,
+            stats.commits,
+            stats.commits as f64 / elapsed,
+            stats.attempts,
+            conflicts,
+            conflict_rate * 100.0,
+            stats.errors
+        );
+        if let Some(latency) = &commit_latency {
+            println!("Commit latency {}", latency);
+        }
+        if let Some(latency) = &attempt_latency {
+            println!("Attempt latency {}", latency);
+        }
+
+        let iteration = IterationResult {
+            label: Some(format!("{:?}", args.mode).to_lowercase()),
+            concurrency: Some(num_writers as usize),
+            elapsed_secs: elapsed,
+            num_requests: stats.attempts,
+            num_objects: Some(stats.commits),
+            latency: commit_latency,
+            errors: stats.errors.to_map(),
+            ..Default::default()
+        };
+        stopped_early =
+            args.errors
+                .should_stop(&stats.unexpected_errors, stats.attempts, &mut results);
+        schedule.record(vec![iteration], &mut results);
+        if stopped_early {
+            break;
+        }
+    }
+    schedule.finish(&mut results);
+
+    // Totals of the measured iterations
+    let commits = results
+        .iterations
+        .iter()
+        .filter_map(|iteration| iteration.num_objects)
+        .sum::<u64>();
+    let attempts = results
+        .iterations
+        .iter()
+        .map(|iteration| iteration.num_requests)
+        .sum::<u64>();
+    let conflicts = results
+        .iterations
+        .iter()
+        .flat_map(|iteration| &iteration.errors)
+        .filter(|(class, _)| {
+            **class == ErrorClass::AlreadyExists.to_string()
+                || **class == ErrorClass::Precondition.to_string()
+        })
+        .map(|(_, count)| count)
+        .sum::<u64>();
+    results
+        .summary
+        .insert("commits".to_string(), commits.into());
+    results
+        .summary
+        .insert("attempts".to_string(), attempts.into());
+    results.summary.insert(
+        "conflict_rate".to_string(),
+        (conflicts as f64 / attempts as f64).into(),
+    );
+    if total_failed_writers > 0 {
+        results
+            .summary
+            .insert("failed_writers".to_string(), total_failed_writers.into());
+    }
+    if non_atomic_iterations > 0 {
+        results.summary.insert(
+            "non_atomic_iterations".to_string(),
+            non_atomic_iterations.into(),
+        );
+    }
+
+    timeline.finish(&mut results);
+    if let Some(results_path) = args.results {
+        results.write(results_path);
+    }
+
+    if args.cleanup {
+        let num_deleted = namespace.cleanup(store.as_ref()).await;
+        println!("Deleted {} objects under {}", num_deleted, namespace.prefix);
+    }
+
+    if stopped_early {
+        std::process::exit(1);
+    }
+}
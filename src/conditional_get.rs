@@ -0,0 +1,269 @@
+use clap::{Parser, ValueEnum};
+use object_store::{path::Path, GetOptions, ObjectMeta, ObjectStore, PutPayload};
+use object_store_bench::{
+    data::random_bytes,
+    errors::{ErrorArgs, ErrorClass, ErrorCounts},
+    namespace::RunNamespace,
+    phase::run_phase,
+    results::{IterationResult, RunResults},
+    store::StoreArgs,
+    timeline::TimelineArgs,
+    timing::TimingArgs,
+};
+use rand::Rng;
+
+/// Measures the cost of cache revalidation with conditional and versioned GETs
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    store: StoreArgs,
+
+    #[command(flatten)]
+    timeline: TimelineArgs,
+
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    num_objects: Option<u64>,
+
+    #[arg(long)]
+    object_size: Option<u64>,
+
+    #[arg(long)]
+    requests_per_case: Option<u64>,
+
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_concurrency: Option<usize>,
+
+    /// Cases to measure (defaults to all of them)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    cases: Vec<Case>,
+
+    /// How many times to measure every case, defaults to 1
+    #[arg(long)]
+    num_iterations: Option<u32>,
+
+    #[command(flatten)]
+    timing: TimingArgs,
+
+    #[command(flatten)]
+    errors: ErrorArgs,
+
+    /// Reuse the namespace of an earlier run instead of creating a new one
+    #[arg(long)]
+    run_id: Option<String>,
+
+    /// Delete everything written under the run's namespace once the run finishes
+    #[arg(long)]
+    cleanup: bool,
+
+    /// Write machine readable results to this file
+    #[arg(long)]
+    results: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Case {
+    /// Unconditional read of the whole object
+    Full,
+    /// `if_none_match` with the current etag, the cache is still valid (304)
+    IfNoneMatchHit,
+    /// `if_none_match` with a stale etag, the object is read again (200)
+    IfNoneMatchMiss,
+    /// `if_match` with a stale etag (412)
+    IfMatchFail,
+    /// `if_modified_since` the object's last modified time (304)
+    IfModifiedSinceHit,
+    /// `if_unmodified_since` before the object was written (412)
+    IfUnmodifiedSinceFail,
+    /// Read the specific version returned when the object was written (200)
+    Version,
+}
+
+#[derive(Debug, PartialEq)]
+enum Expected {
+    Body,
+    NotModified,
+    Precondition,
+}
+
+const STALE_ETAG: &str = "\"object_store_bench-stale\"";
+
+impl Case {
+    fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    /// Builds the request options for `meta`, or `None` if the store can't do this case
+    fn options(&self, meta: &ObjectMeta) -> Option<(GetOptions, Expected)> {
+        let mut options = GetOptions::default();
+        let expected = match self {
+            Case::Full => Expected::Body,
+            Case::IfNoneMatchHit => {
+                options.if_none_match = Some(meta.e_tag.clone()?);
+                Expected::NotModified
+            }
+            Case::IfNoneMatchMiss => {
+                options.if_none_match = Some(STALE_ETAG.to_string());
+                Expected::Body
+            }
+            Case::IfMatchFail => {
+                options.if_match = Some(STALE_ETAG.to_string());
+                Expected::Precondition
+            }
+            Case::IfModifiedSinceHit => {
+                options.if_modified_since = Some(meta.last_modified);
+                Expected::NotModified
+            }
+            Case::IfUnmodifiedSinceFail => {
+                options.if_unmodified_since = Some(meta.last_modified - chrono::Duration::days(1));
+                Expected::Precondition
+            }
+            Case::Version => {
+                options.version = Some(meta.version.clone()?);
+                Expected::Body
+            }
+        };
+        Some((options, expected))
+    }
+}
+
+async fn conditional_get(
+    store: &dyn ObjectStore,
+    path: &Path,
+    options: GetOptions,
+    expected: Expected,
+) -> object_store::Result<u64> {
+    let outcome = match store.get_opts(path, options).await {
+        Ok(result) => Ok(result.bytes().await?.len() as u64),
+        Err(e) => Err(e),
+    };
+    let actual = match &outcome {
+        Ok(_) => Expected::Body,
+        Err(e) => match ErrorClass::of(e) {
+            ErrorClass::NotModified => Expected::NotModified,
+            ErrorClass::Precondition => Expected::Precondition,
+            _ => return outcome,
+        },
+    };
+    if actual != expected {
+        return Err(object_store::Error::Generic {
+            store: "conditional_get",
+            source: format!("expected {:?} but got {:?}", expected, actual).into(),
+        });
+    }
+    // A 304 or 412 is the successful outcome of a revalidation
+    Ok(outcome.unwrap_or(0))
+}
+
+#[tokio::main]
+async fn main() {
+    object_store_bench::retries::init_logging();
+
+    let args = Args::parse();
+
+    let num_objects = args.num_objects.unwrap_or(100);
+    let object_size = args.object_size.unwrap_or(1024 * 1024);
+    let requests_per_case = args.requests_per_case.unwrap_or(1000);
+    let max_concurrency = args.max_concurrency.unwrap_or(16);
+    let num_iterations = args.num_iterations.unwrap_or(1);
+    let cases = if args.cases.is_empty() {
+        Case::value_variants().to_vec()
+    } else {
+        args.cases
+    };
+
+    let (store, base_path) = args.store.build();
+    let timeline = args.timeline.start();
+    let store = timeline.wrap(store);
+    let namespace = RunNamespace::new(&base_path, args.run_id);
+    println!("Run id: {}", namespace.run_id);
+
+    println!("Writing {} objects of {} bytes", num_objects, object_size);
+    let data = random_bytes(object_size);
+    let path = |idx: u64| namespace.path(&format!("cached/{}", idx));
+    let mut metas = Vec::with_capacity(num_objects as usize);
+    for idx in 0..num_objects {
+        let path = path(idx);
+        let written = async {
+            let put = store
+                .put(&path, PutPayload::from_bytes(data.clone()))
+                .await?;
+            Ok::<_, object_store::Error>((put, store.head(&path).await?))
+        };
+        let (put, mut meta) = match written.await {
+            Ok(written) => written,
+            Err(e) => {
+                eprintln!("Writing {} failed: {}", path, e);
+                std::process::exit(1);
+            }
+        };
+        // Some stores only report the version on the write
+        meta.version = meta.version.or(put.version);
+        metas.push(meta);
+    }
+
+    let mut results = RunResults::new(
+        "conditional_get",
+        &namespace.run_id,
+        namespace.prefix.as_ref(),
+    );
+    args.store.record(&mut results);
+    let mut schedule = args.timing.schedule(num_iterations);
+    let mut stopped_early = false;
+    while schedule.next_iteration() {
+        let mut iteration = Vec::new();
+        let mut errors = ErrorCounts::default();
+        let mut num_requests = 0;
+        for &case in &cases {
+            if metas.iter().any(|meta| case.options(meta).is_none()) {
+                println!(
+                    "Skipping {}, the store doesn't report the etag or version it needs",
+                    case.name()
+                );
+                continue;
+            }
+            let mut rng = rand::thread_rng();
+            let targets = (0..requests_per_case)
+                .map(|_| rng.gen_range(0..num_objects))
+                .collect::<Vec<_>>();
+            let mut outcome = run_phase(requests_per_case, max_concurrency, |request| {
+                let store = store.clone();
+                let meta = &metas[targets[request as usize] as usize];
+                let location = meta.location.clone();
+                let (options, expected) = case.options(meta).unwrap();
+                async move { conditional_get(store.as_ref(), &location, options, expected).await }
+            })
+            .await;
+            if args.errors.include_failed_latencies {
+                outcome.include_failed_latencies();
+            }
+            errors.merge(&outcome.errors);
+            num_requests += outcome.num_requests();
+            iteration.push(IterationResult {
+                object_size: Some(object_size),
+                concurrency: Some(max_concurrency),
+                ..outcome.report(&case.name())
+            });
+        }
+        stopped_early = args.errors.should_stop(&errors, num_requests, &mut results);
+        schedule.record(iteration, &mut results);
+        if stopped_early {
+            break;
+        }
+    }
+    schedule.finish(&mut results);
+
+    timeline.finish(&mut results);
+    if let Some(results_path) = args.results {
+        results.write(results_path);
+    }
+
+    if args.cleanup {
+        let num_deleted = namespace.cleanup(store.as_ref()).await;
+        println!("Deleted {} objects under {}", num_deleted, namespace.prefix);
+    }
+
+    if stopped_early {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,517 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::{path::Path, ObjectStore, PutPayload};
+use object_store_bench::{
+    args::parse_fraction,
+    errors::{ErrorArgs, ErrorClass, ErrorCounts},
+    namespace::RunNamespace,
+    results::{IterationResult, RunResults},
+    store::StoreArgs,
+    timeline::TimelineArgs,
+};
+use rand::Rng;
+use serde::Serialize;
+
+/// Checks read-after-write, read-after-delete and list-after-write consistency while
+/// objects are concurrently written, overwritten and deleted
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    store: StoreArgs,
+
+    #[command(flatten)]
+    timeline: TimelineArgs,
+
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    num_keys: Option<usize>,
+
+    #[arg(long)]
+    num_writers: Option<usize>,
+
+    #[arg(long)]
+    num_readers: Option<usize>,
+
+    #[arg(long)]
+    num_listers: Option<usize>,
+
+    /// Fraction of writes that delete the key instead of (over)writing it
+    #[arg(long, value_parser = parse_fraction)]
+    delete_fraction: Option<f64>,
+
+    #[arg(long)]
+    duration_secs: Option<u64>,
+
+    #[command(flatten)]
+    errors: ErrorArgs,
+
+    /// Reuse the namespace of an earlier run instead of creating a new one
+    #[arg(long)]
+    run_id: Option<String>,
+
+    /// Delete everything written under the run's namespace once the run finishes
+    #[arg(long)]
+    cleanup: bool,
+
+    /// Write machine readable results to this file
+    #[arg(long)]
+    results: Option<String>,
+}
+
+/// What the writers know about one key.  Every key has exactly one writer so its operations
+/// are totally ordered by `seq`.
+#[derive(Debug, Default, Clone, Copy)]
+struct KeyState {
+    /// Sequence number of the last write or delete the store acknowledged (0 = never written)
+    committed_seq: u64,
+    committed_exists: bool,
+    /// Sequence number of the last delete that was started, acknowledged or not
+    delete_started_seq: u64,
+    /// Sequence number of the last put that was started, acknowledged or not
+    put_started_seq: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Anomaly {
+    kind: &'static str,
+    key: usize,
+    /// Milliseconds since the unix epoch when the anomaly was detected
+    unix_millis: u128,
+    /// Seconds since the start of the run
+    elapsed_secs: f64,
+    detail: String,
+}
+
+struct Checker {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+    keys: Mutex<Vec<KeyState>>,
+    next_seq: AtomicU64,
+    start: Instant,
+    anomalies: Mutex<Vec<Anomaly>>,
+    /// Failed requests, which don't tell us anything about consistency
+    errors: Mutex<ErrorCounts>,
+    num_writes: AtomicU64,
+    num_deletes: AtomicU64,
+    num_reads: AtomicU64,
+    num_lists: AtomicU64,
+}
+
+impl Checker {
+    fn new(store: Arc<dyn ObjectStore>, prefix: Path, num_keys: usize) -> Self {
+        Self {
+            store,
+            prefix,
+            keys: Mutex::new(vec![KeyState::default(); num_keys]),
+            next_seq: AtomicU64::new(1),
+            start: Instant::now(),
+            anomalies: Mutex::new(Vec::new()),
+            errors: Mutex::default(),
+            num_writes: AtomicU64::new(0),
+            num_deletes: AtomicU64::new(0),
+            num_reads: AtomicU64::new(0),
+            num_lists: AtomicU64::new(0),
+        }
+    }
+
+    fn path(&self, key: usize) -> Path {
+        self.prefix.child(key.to_string())
+    }
+
+    fn key_state(&self, key: usize) -> KeyState {
+        self.keys.lock().unwrap()[key]
+    }
+
+    fn record_error(&self, what: &str, error: &object_store::Error) {
+        log::warn!("{} failed: {}", what, error);
+        self.errors.lock().unwrap().record(error);
+    }
+
+    fn report(&self, kind: &'static str, key: usize, detail: String) {
+        let anomaly = Anomaly {
+            kind,
+            key,
+            unix_millis: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis(),
+            elapsed_secs: self.start.elapsed().as_secs_f64(),
+            detail,
+        };
+        log::warn!("{:?}", anomaly);
+        self.anomalies.lock().unwrap().push(anomaly);
+    }
+
+    async fn write_one(&self, key: usize, delete: bool) {
+        let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        {
+            let mut keys = self.keys.lock().unwrap();
+            if delete {
+                keys[key].delete_started_seq = seq;
+            } else {
+                keys[key].put_started_seq = seq;
+            }
+        }
+        let path = self.path(key);
+        let result = if delete {
+            self.num_deletes.fetch_add(1, Ordering::Relaxed);
+            match self.store.delete(&path).await {
+                Err(e) if ErrorClass::of(&e) == ErrorClass::NotFound => Ok(()),
+                result => result,
+            }
+        } else {
+            self.num_writes.fetch_add(1, Ordering::Relaxed);
+            self.store
+                .put(&path, PutPayload::from(seq.to_string()))
+                .await
+                .map(|_| ())
+        };
+        match result {
+            Ok(()) => {
+                let mut keys = self.keys.lock().unwrap();
+                keys[key].committed_seq = seq;
+                keys[key].committed_exists = !delete;
+            }
+            // The operation may or may not have been applied, which the started sequence
+            // numbers already allow for
+            Err(e) => self.record_error(&format!("Write of key {}", key), &e),
+        }
+    }
+
+    async fn read_one(&self, key: usize) {
+        self.num_reads.fetch_add(1, Ordering::Relaxed);
+        let before = self.key_state(key);
+        let observed = match self.store.get(&self.path(key)).await {
+            Ok(result) => match result.bytes().await {
+                Ok(bytes) => Some(String::from_utf8_lossy(&bytes).parse::<u64>().unwrap()),
+                Err(e) => {
+                    self.record_error(&format!("Read of key {}", key), &e);
+                    return;
+                }
+            },
+            Err(e) if ErrorClass::of(&e) == ErrorClass::NotFound => None,
+            Err(e) => {
+                self.record_error(&format!("Read of key {}", key), &e);
+                return;
+            }
+        };
+        let after = self.key_state(key);
+        match observed {
+            Some(seq) if seq < before.committed_seq => self.report(
+                "stale_read",
+                key,
+                format!(
+                    "read version {} after version {} was committed",
+                    seq, before.committed_seq
+                ),
+            ),
+            None if before.committed_exists && after.delete_started_seq < before.committed_seq => {
+                self.report(
+                    "missing_read",
+                    key,
+                    format!(
+                        "not found after version {} was committed and before any delete",
+                        before.committed_seq
+                    ),
+                )
+            }
+            _ => {}
+        }
+    }
+
+    async fn list_once(&self) {
+        self.num_lists.fetch_add(1, Ordering::Relaxed);
+        let before = self.keys.lock().unwrap().clone();
+        let listed = match self
+            .store
+            .list(Some(&self.prefix))
+            .map_ok(|meta| meta.location)
+            .try_collect::<HashSet<_>>()
+            .await
+        {
+            Ok(listed) => listed,
+            Err(e) => {
+                self.record_error("Listing", &e);
+                return;
+            }
+        };
+        let after = self.keys.lock().unwrap().clone();
+        for (key, (before, after)) in before.iter().zip(after.iter()).enumerate() {
+            let present = listed.contains(&self.path(key));
+            if before.committed_exists
+                && !present
+                && after.delete_started_seq < before.committed_seq
+            {
+                self.report(
+                    "missing_list_entry",
+                    key,
+                    format!(
+                        "not listed after version {} was committed and before any delete",
+                        before.committed_seq
+                    ),
+                );
+            }
+            if !before.committed_exists
+                && before.committed_seq > 0
+                && present
+                && after.put_started_seq < before.committed_seq
+            {
+                self.report(
+                    "deleted_list_entry",
+                    key,
+                    format!(
+                        "listed after delete {} was committed and before any write",
+                        before.committed_seq
+                    ),
+                );
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    object_store_bench::retries::init_logging();
+
+    let args = Args::parse();
+
+    let num_keys = args.num_keys.unwrap_or(100);
+    let num_writers = args.num_writers.unwrap_or(4).max(1);
+    let num_readers = args.num_readers.unwrap_or(4);
+    let num_listers = args.num_listers.unwrap_or(1);
+    let delete_fraction = args.delete_fraction.unwrap_or(0.1);
+    let duration = Duration::from_secs(args.duration_secs.unwrap_or(30));
+
+    let (store, base_path) = args.store.build();
+    let timeline = args.timeline.start();
+    let store = timeline.wrap(store);
+    let namespace = RunNamespace::new(&base_path, args.run_id);
+    println!("Run id: {}", namespace.run_id);
+
+    let checker = Arc::new(Checker::new(
+        store.clone(),
+        namespace.path("consistency"),
+        num_keys,
+    ));
+    let deadline = Instant::now() + duration;
+
+    let mut tasks = Vec::new();
+    for writer in 0..num_writers {
+        let checker = checker.clone();
+        // Each writer owns the keys congruent to its index
+        let keys = (writer..num_keys).step_by(num_writers).collect::<Vec<_>>();
+        if keys.is_empty() {
+            continue;
+        }
+        tasks.push(tokio::spawn(async move {
+            while Instant::now() < deadline {
+                // Stores like `InMemory` never yield, so make room for the other tasks
+                tokio::task::yield_now().await;
+                let (key, delete) = {
+                    let mut rng = rand::thread_rng();
+                    (
+                        keys[rng.gen_range(0..keys.len())],
+                        rng.gen_bool(delete_fraction),
+                    )
+                };
+                checker.write_one(key, delete).await;
+            }
+        }));
+    }
+    for _ in 0..num_readers {
+        let checker = checker.clone();
+        tasks.push(tokio::spawn(async move {
+            while Instant::now() < deadline {
+                tokio::task::yield_now().await;
+                let key = rand::thread_rng().gen_range(0..num_keys);
+                checker.read_one(key).await;
+            }
+        }));
+    }
+    for _ in 0..num_listers {
+        let checker = checker.clone();
+        tasks.push(tokio::spawn(async move {
+            while Instant::now() < deadline {
+                tokio::task::yield_now().await;
+                checker.list_once().await;
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+    let elapsed = checker.start.elapsed().as_secs_f64();
+
+    let anomalies = checker.anomalies.lock().unwrap().clone();
+    let count = |kind: &str| anomalies.iter().filter(|a| a.kind == kind).count();
+    println!(
+        "Ran for {:?} seconds: {} writes, {} deletes, {} reads, {} lists",
+        elapsed,
+        checker.num_writes.load(Ordering::Relaxed),
+        checker.num_deletes.load(Ordering::Relaxed),
+        checker.num_reads.load(Ordering::Relaxed),
+        checker.num_lists.load(Ordering::Relaxed),
+    );
+    println!(
+        "Anomalies: {} stale reads, {} missing reads, {} missing list entries, {} deleted list entries",
+        count("stale_read"),
+        count("missing_read"),
+        count("missing_list_entry"),
+        count("deleted_list_entry"),
+    );
+
+    let errors = checker.errors.lock().unwrap().clone();
+    if !errors.is_empty() {
+        println!("Failed requests: {}", errors);
+    }
+
+    let mut results = RunResults::new("consistency", &namespace.run_id, namespace.prefix.as_ref());
+    args.store.record(&mut results);
+    let num_requests = checker.num_writes.load(Ordering::Relaxed)
+        + checker.num_deletes.load(Ordering::Relaxed)
+        + checker.num_reads.load(Ordering::Relaxed)
+        + checker.num_lists.load(Ordering::Relaxed);
+    let stopped_early = args.errors.should_stop(&errors, num_requests, &mut results);
+    results.iterations.push(IterationResult {
+        elapsed_secs: elapsed,
+        num_requests,
+        errors: errors.to_map(),
+        ..Default::default()
+    });
+    results.summary.insert(
+        "anomalies".to_string(),
+        serde_json::to_value(&anomalies).unwrap(),
+    );
+
+    timeline.finish(&mut results);
+    if let Some(results_path) = args.results {
+        results.write(results_path);
+    }
+
+    if args.cleanup {
+        let num_deleted = namespace.cleanup(store.as_ref()).await;
+        println!("Deleted {} objects under {}", num_deleted, namespace.prefix);
+    }
+
+    if !anomalies.is_empty() {
+        std::process::exit(2);
+    }
+    if stopped_early {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+
+    use super::*;
+
+    /// A checker over an in-memory store holding `objects` (key, version), believing `keys`
+    async fn checker(objects: &[(usize, u64)], keys: Vec<KeyState>) -> Checker {
+        let checker = Checker::new(Arc::new(InMemory::new()), Path::from("keys"), keys.len());
+        for &(key, seq) in objects {
+            checker
+                .store
+                .put(&checker.path(key), PutPayload::from(seq.to_string()))
+                .await
+                .unwrap();
+        }
+        *checker.keys.lock().unwrap() = keys;
+        checker
+    }
+
+    fn anomalies(checker: &Checker) -> Vec<(&'static str, usize)> {
+        let anomalies = checker.anomalies.lock().unwrap();
+        anomalies.iter().map(|a| (a.kind, a.key)).collect()
+    }
+
+    fn written(seq: u64) -> KeyState {
+        KeyState {
+            committed_seq: seq,
+            committed_exists: true,
+            put_started_seq: seq,
+            ..Default::default()
+        }
+    }
+
+    fn deleted(seq: u64) -> KeyState {
+        KeyState {
+            committed_seq: seq,
+            committed_exists: false,
+            delete_started_seq: seq,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn consistent_store_has_no_anomalies() {
+        let checker = checker(&[], vec![KeyState::default(); 3]).await;
+        checker.write_one(0, false).await;
+        checker.write_one(1, false).await;
+        checker.write_one(1, true).await;
+        for key in 0..3 {
+            checker.read_one(key).await;
+        }
+        checker.list_once().await;
+        assert_eq!(anomalies(&checker), vec![]);
+        assert_eq!(checker.num_reads.load(Ordering::Relaxed), 3);
+        assert_eq!(checker.num_lists.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn reads_of_an_older_version_are_stale() {
+        let checker = checker(&[(0, 1), (1, 2)], vec![written(2), written(2)]).await;
+        checker.read_one(0).await;
+        checker.read_one(1).await;
+        assert_eq!(anomalies(&checker), vec![("stale_read", 0)]);
+    }
+
+    #[tokio::test]
+    async fn reads_that_miss_a_written_key_are_missing() {
+        let delete_in_flight = KeyState {
+            delete_started_seq: 3,
+            ..written(2)
+        };
+        let checker = checker(&[], vec![written(2), delete_in_flight, deleted(2)]).await;
+        for key in 0..3 {
+            checker.read_one(key).await;
+        }
+        assert_eq!(anomalies(&checker), vec![("missing_read", 0)]);
+    }
+
+    #[tokio::test]
+    async fn lists_without_a_written_key_miss_it() {
+        let delete_in_flight = KeyState {
+            delete_started_seq: 3,
+            ..written(2)
+        };
+        let checker = checker(&[(2, 2)], vec![written(2), delete_in_flight, written(2)]).await;
+        checker.list_once().await;
+        assert_eq!(anomalies(&checker), vec![("missing_list_entry", 0)]);
+    }
+
+    #[tokio::test]
+    async fn lists_with_a_deleted_key_show_it() {
+        let put_in_flight = KeyState {
+            put_started_seq: 4,
+            ..deleted(3)
+        };
+        let checker = checker(
+            &[(0, 2), (1, 2)],
+            vec![deleted(3), put_in_flight, KeyState::default()],
+        )
+        .await;
+        checker.list_once().await;
+        assert_eq!(anomalies(&checker), vec![("deleted_list_entry", 0)]);
+    }
+}
@@ -0,0 +1,273 @@
+use std::time::Instant;
+
+use clap::{Parser, ValueEnum};
+use futures::{StreamExt, TryStreamExt};
+use object_store::{path::Path, ObjectStore, PutPayload};
+use object_store_bench::{
+    data::random_bytes,
+    errors::{ErrorArgs, ErrorCounts},
+    namespace::RunNamespace,
+    phase::{run_phase, PhaseOutcome},
+    results::{IterationResult, RunResults},
+    store::StoreArgs,
+    timeline::TimelineArgs,
+    timing::TimingArgs,
+};
+
+/// Measures server side copy, rename and bulk delete against re-uploading the data
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    store: StoreArgs,
+
+    #[command(flatten)]
+    timeline: TimelineArgs,
+
+    #[arg(long)]
+    num_objects: Option<u64>,
+
+    #[arg(long)]
+    object_size: Option<u64>,
+
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_concurrency: Option<usize>,
+
+    /// Operations to measure, in order (defaults to all of them)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    operations: Vec<Operation>,
+
+    #[arg(long)]
+    num_iterations: Option<u32>,
+
+    #[command(flatten)]
+    timing: TimingArgs,
+
+    #[command(flatten)]
+    errors: ErrorArgs,
+
+    /// Reuse the namespace of an earlier run instead of creating a new one
+    #[arg(long)]
+    run_id: Option<String>,
+
+    /// Delete everything written under the run's namespace once the run finishes
+    #[arg(long)]
+    cleanup: bool,
+
+    /// Write machine readable results to this file
+    #[arg(long)]
+    results: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Operation {
+    Copy,
+    CopyIfNotExists,
+    /// Download the object and upload it again, the client side alternative to `copy`
+    Reupload,
+    Rename,
+    /// Delete everything the other operations wrote with `delete_stream`
+    Delete,
+}
+
+impl Operation {
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::Copy => "copy",
+            Operation::CopyIfNotExists => "copy_if_not_exists",
+            Operation::Reupload => "reupload",
+            Operation::Rename => "rename",
+            Operation::Delete => "delete_stream",
+        }
+    }
+}
+
+/// Deletes everything under `prefix`, counting each object deleted as a request
+async fn bulk_delete(store: &dyn ObjectStore, prefix: &Path) -> PhaseOutcome {
+    let mut outcome = PhaseOutcome::default();
+    let start = Instant::now();
+    let locations = match store
+        .list(Some(prefix))
+        .map_ok(|meta| meta.location)
+        .try_collect::<Vec<_>>()
+        .await
+    {
+        Ok(locations) => locations,
+        Err(e) => {
+            outcome.record(start.elapsed().as_secs_f64(), &Err(e));
+            return outcome;
+        }
+    };
+
+    let start = Instant::now();
+    let deleted = store
+        .delete_stream(futures::stream::iter(locations.into_iter().map(Ok)).boxed())
+        .collect::<Vec<_>>()
+        .await;
+    outcome.elapsed_secs = start.elapsed().as_secs_f64();
+    // Stores batch the deletes, so there is no latency per object
+    for result in deleted {
+        outcome.record(0.0, &result.map(|_| 0));
+    }
+    outcome
+}
+
+/// Prints the bulk delete's summary line and converts it to a result
+fn report_delete(outcome: PhaseOutcome) -> IterationResult {
+    let num_objects = outcome.num_requests();
+    println!(
+        "Total delete_stream took {:?} seconds ({} objects, {} objects/s{})",
+        outcome.elapsed_secs,
+        num_objects,
+        num_objects as f64 / outcome.elapsed_secs,
+        outcome.errors.suffix()
+    );
+    IterationResult {
+        label: Some(Operation::Delete.name().to_string()),
+        elapsed_secs: outcome.elapsed_secs,
+        num_requests: num_objects,
+        num_objects: Some(num_objects),
+        errors: outcome.errors.to_map(),
+        ..Default::default()
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    object_store_bench::retries::init_logging();
+
+    let args = Args::parse();
+
+    let num_objects = args.num_objects.unwrap_or(100);
+    let object_size = args.object_size.unwrap_or(1024 * 1024);
+    let max_concurrency = args.max_concurrency.unwrap_or(16);
+    let num_iterations = args.num_iterations.unwrap_or(1);
+    let operations = if args.operations.is_empty() {
+        vec![
+            Operation::Copy,
+            Operation::CopyIfNotExists,
+            Operation::Reupload,
+            Operation::Rename,
+            Operation::Delete,
+        ]
+    } else {
+        args.operations
+    };
+
+    let (store, base_path) = args.store.build();
+    let timeline = args.timeline.start();
+    let store = timeline.wrap(store);
+    let namespace = RunNamespace::new(&base_path, args.run_id);
+    println!("Run id: {}", namespace.run_id);
+
+    println!("Writing {} objects of {} bytes", num_objects, object_size);
+    let data = random_bytes(object_size);
+    let source = |idx: u64| namespace.path(&format!("source/{}", idx));
+    let populate = run_phase(num_objects, max_concurrency, |idx| {
+        let store = store.clone();
+        let path = source(idx);
+        let payload = PutPayload::from_bytes(data.clone());
+        async move { store.put(&path, payload).await.map(|_| object_size) }
+    })
+    .await;
+
+    let mut results = RunResults::new("copy", &namespace.run_id, namespace.prefix.as_ref());
+    args.store.record(&mut results);
+    let mut stopped_early =
+        args.errors
+            .should_stop(&populate.errors, populate.num_requests(), &mut results);
+    let mut schedule = args.timing.schedule(num_iterations);
+    let mut iteration_idx = 0;
+    while !stopped_early && schedule.next_iteration() {
+        let iteration_prefix = namespace.path(&format!("iter-{}", iteration_idx));
+        iteration_idx += 1;
+        let mut iteration = Vec::new();
+        let dest =
+            |op: Operation, idx: u64| iteration_prefix.child(op.name()).child(idx.to_string());
+
+        let mut errors = ErrorCounts::default();
+        let mut num_requests = 0;
+        for &op in &operations {
+            let mut outcome = match op {
+                Operation::Copy | Operation::CopyIfNotExists => {
+                    run_phase(num_objects, max_concurrency, |idx| {
+                        let store = store.clone();
+                        let (from, to) = (source(idx), dest(op, idx));
+                        async move {
+                            if op == Operation::Copy {
+                                store.copy(&from, &to).await?;
+                            } else {
+                                store.copy_if_not_exists(&from, &to).await?;
+                            }
+                            Ok(object_size)
+                        }
+                    })
+                    .await
+                }
+                Operation::Reupload => {
+                    run_phase(num_objects, max_concurrency, |idx| {
+                        let store = store.clone();
+                        let (from, to) = (source(idx), dest(op, idx));
+                        async move {
+                            let bytes = store.get(&from).await?.bytes().await?;
+                            let num_bytes = bytes.len() as u64;
+                            store.put(&to, PutPayload::from_bytes(bytes)).await?;
+                            Ok(num_bytes)
+                        }
+                    })
+                    .await
+                }
+                Operation::Rename => {
+                    // Rename moves its source away, so stage copies of the sources first
+                    let staged = |idx: u64| iteration_prefix.child("staged").child(idx.to_string());
+                    let staging = run_phase(num_objects, max_concurrency, |idx| {
+                        let store = store.clone();
+                        let (from, to) = (source(idx), staged(idx));
+                        async move { store.copy(&from, &to).await.map(|_| 0) }
+                    })
+                    .await;
+                    errors.merge(&staging.errors);
+                    num_requests += staging.num_requests();
+                    run_phase(num_objects, max_concurrency, |idx| {
+                        let store = store.clone();
+                        let (from, to) = (staged(idx), dest(op, idx));
+                        async move { store.rename(&from, &to).await.map(|_| object_size) }
+                    })
+                    .await
+                }
+                Operation::Delete => bulk_delete(store.as_ref(), &iteration_prefix).await,
+            };
+            if args.errors.include_failed_latencies {
+                outcome.include_failed_latencies();
+            }
+            errors.merge(&outcome.errors);
+            num_requests += outcome.num_requests();
+            let result = match op {
+                Operation::Delete => report_delete(outcome),
+                _ => outcome.report(op.name()),
+            };
+            iteration.push(IterationResult {
+                object_size: Some(object_size),
+                concurrency: Some(max_concurrency),
+                ..result
+            });
+        }
+        stopped_early = args.errors.should_stop(&errors, num_requests, &mut results);
+        schedule.record(iteration, &mut results);
+    }
+    schedule.finish(&mut results);
+
+    timeline.finish(&mut results);
+    if let Some(results_path) = args.results {
+        results.write(results_path);
+    }
+
+    if args.cleanup {
+        let num_deleted = namespace.cleanup(store.as_ref()).await;
+        println!("Deleted {} objects under {}", num_deleted, namespace.prefix);
+    }
+
+    if stopped_early {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    fmt::Write as _,
+    io::{IsTerminal, Write as _},
+    time::Duration,
+};
+
+use crate::{stats::LatencySummary, timeline::TimelinePoint};
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// Redraws a summary of the running workload in place, once per timeline interval
+#[derive(Debug)]
+pub struct Dashboard {
+    interval_secs: f64,
+    total_ops: u64,
+    total_bytes: u64,
+    total_errors: u64,
+    total_retries: u64,
+    total_throttled: u64,
+}
+
+impl Dashboard {
+    /// Returns `None` when stdout isn't a terminal, in which case callers should fall back to
+    /// printing plain lines
+    pub fn new(interval_secs: f64) -> Option<Self> {
+        std::io::stdout().is_terminal().then_some(Self {
+            interval_secs,
+            total_ops: 0,
+            total_bytes: 0,
+            total_errors: 0,
+            total_retries: 0,
+            total_throttled: 0,
+        })
+    }
+
+    /// Draws the dashboard for the interval that just ended.  `expected_bytes` is how many bytes
+    /// the whole run should move, if the workload knows.
+    pub fn update(
+        &mut self,
+        point: &TimelinePoint,
+        latency: Option<&LatencySummary>,
+        expected_bytes: Option<u64>,
+    ) {
+        self.total_ops += point.ops;
+        self.total_bytes += point.bytes;
+        self.total_errors += point.errors;
+        self.total_retries += point.retries;
+        self.total_throttled += point.throttled;
+        let frame = self.render(point, latency, expected_bytes);
+        let mut stdout = std::io::stdout().lock();
+        // Clear the screen and draw from the top left, wiping any lines printed since the
+        // last frame
+        let _ = write!(stdout, "\x1b[2J\x1b[H{}", frame);
+        let _ = stdout.flush();
+    }
+
+    fn render(
+        &self,
+        point: &TimelinePoint,
+        latency: Option<&LatencySummary>,
+        expected_bytes: Option<u64>,
+    ) -> String {
+        let elapsed = point.t_secs;
+        let mut frame = String::new();
+        let _ = writeln!(frame, "object_store_bench      elapsed {}", clock(elapsed));
+        let _ = writeln!(frame);
+        let _ = writeln!(
+            frame,
+            "throughput  {:>10.1} MiB/s   (average {:.1} MiB/s)",
+            point.bytes as f64 / self.interval_secs / MIB,
+            self.total_bytes as f64 / elapsed / MIB
+        );
+        let _ = writeln!(
+            frame,
+            "requests    {:>10.1} ops/s   (average {:.1} ops/s)",
+            point.ops as f64 / self.interval_secs,
+            self.total_ops as f64 / elapsed
+        );
+        let _ = match latency {
+            Some(latency) => writeln!(
+                frame,
+                "latency     p50 {} p90 {} p99 {} max {}",
+                millis(latency.p50),
+                millis(latency.p90),
+                millis(latency.p99),
+                millis(latency.max)
+            ),
+            None => writeln!(frame, "latency     -"),
+        };
+        let _ = writeln!(frame, "in flight   {:>10}", point.in_flight);
+        let _ = writeln!(
+            frame,
+            "errors      {:>10}   (total {})",
+            point.errors, self.total_errors
+        );
+        let _ = writeln!(
+            frame,
+            "retries     {:>10}   (total {}, {} throttled)",
+            point.retries, self.total_retries, self.total_throttled
+        );
+        match expected_bytes {
+            Some(expected) if expected > 0 => {
+                let done = (self.total_bytes as f64 / expected as f64).min(1.0);
+                let filled = (done * 40.0) as usize;
+                let rate = self.total_bytes as f64 / elapsed;
+                let eta = if rate > 0.0 {
+                    clock(expected.saturating_sub(self.total_bytes) as f64 / rate)
+                } else {
+                    "-".to_string()
+                };
+                let _ = writeln!(
+                    frame,
+                    "progress    [{}{}] {:5.1}%  {:.1} / {:.1} MiB  ETA {}",
+                    "#".repeat(filled),
+                    "-".repeat(40 - filled),
+                    done * 100.0,
+                    self.total_bytes as f64 / MIB,
+                    expected as f64 / MIB,
+                    eta
+                );
+            }
+            _ => {
+                let _ = writeln!(
+                    frame,
+                    "transferred {:>10.1} MiB",
+                    self.total_bytes as f64 / MIB
+                );
+            }
+        }
+        frame
+    }
+}
+
+fn millis(secs: f64) -> String {
+    format!("{:.3}ms", secs * 1000.0)
+}
+
+fn clock(secs: f64) -> String {
+    let secs = Duration::from_secs_f64(secs.max(0.0)).as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
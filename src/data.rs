@@ -0,0 +1,9 @@
+use bytes::Bytes;
+use rand::{thread_rng, RngCore};
+
+/// Generates `size` bytes of random (incompressible) data to upload
+pub fn random_bytes(size: u64) -> Bytes {
+    let mut data = bytes::BytesMut::zeroed(size as usize);
+    thread_rng().fill_bytes(&mut data);
+    data.freeze()
+}
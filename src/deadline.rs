@@ -0,0 +1,64 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+
+use crate::args::parse_secs;
+
+/// Options for giving up on slow requests, the way a query engine with a deadline would
+#[derive(clap::Args, Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeadlineArgs {
+    /// Give up on a request after this many seconds, cancelling it rather than retrying
+    #[arg(long, value_parser = parse_secs)]
+    #[serde(deserialize_with = "crate::args::deserialize_secs")]
+    pub op_timeout: Option<f64>,
+
+    /// Cancel whatever is still outstanding this many seconds into each iteration
+    #[arg(long, value_parser = parse_secs)]
+    #[serde(deserialize_with = "crate::args::deserialize_secs")]
+    pub iteration_deadline: Option<f64>,
+}
+
+impl DeadlineArgs {
+    /// Runs `fut` for at most `--op-timeout`, returning `None` if it was cancelled
+    pub async fn limit<F: Future>(&self, fut: F) -> Option<F::Output> {
+        match self.op_timeout {
+            Some(timeout) => tokio::time::timeout(Duration::from_secs_f64(timeout), fut)
+                .await
+                .ok(),
+            None => Some(fut.await),
+        }
+    }
+
+    /// Passes each item of `stream` to `on_item` until it ends or `--iteration-deadline` after
+    /// `start`, dropping the stream (and so cancelling anything in flight) at the deadline.
+    /// Returns whether the deadline cut it short.
+    pub async fn drain<S: Stream>(
+        &self,
+        stream: S,
+        start: Instant,
+        mut on_item: impl FnMut(S::Item),
+    ) -> bool {
+        let deadline = self
+            .iteration_deadline
+            .map(|deadline| (start + Duration::from_secs_f64(deadline)).into());
+        let mut stream = std::pin::pin!(stream);
+        loop {
+            let next = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, stream.next()).await {
+                    Ok(next) => next,
+                    Err(_) => return true,
+                },
+                None => stream.next().await,
+            };
+            match next {
+                Some(item) => on_item(item),
+                None => return false,
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::{fmt, str::FromStr};
+
+use rand::Rng;
+
+/// Distribution of object or request sizes, parsed from the command line
+///
+/// * `fixed:<size>` always picks `size`
+/// * `uniform:<min>-<max>` picks uniformly between `min` and `max` (inclusive)
+/// * `choice:<a>,<b>,...` picks one of the listed sizes with equal probability
+#[derive(Debug, Clone, PartialEq)]
+pub enum SizeDistribution {
+    Fixed(u64),
+    Uniform(u64, u64),
+    Choice(Vec<u64>),
+}
+
+impl SizeDistribution {
+    pub fn sample(&self, rng: &mut impl Rng) -> u64 {
+        match self {
+            SizeDistribution::Fixed(size) => *size,
+            SizeDistribution::Uniform(min, max) => rng.gen_range(*min..=*max),
+            SizeDistribution::Choice(sizes) => sizes[rng.gen_range(0..sizes.len())],
+        }
+    }
+
+    pub fn max(&self) -> u64 {
+        match self {
+            SizeDistribution::Fixed(size) => *size,
+            SizeDistribution::Uniform(_, max) => *max,
+            SizeDistribution::Choice(sizes) => sizes.iter().copied().max().unwrap_or(0),
+        }
+    }
+}
+
+impl FromStr for SizeDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_size = |size: &str| {
+            size.trim()
+                .parse::<u64>()
+                .map_err(|e| format!("invalid size '{}': {}", size, e))
+        };
+        let (kind, params) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected <kind>:<params>, got '{}'", s))?;
+        match kind {
+            "fixed" => Ok(SizeDistribution::Fixed(parse_size(params)?)),
+            "uniform" => {
+                let (min, max) = params
+                    .split_once('-')
+                    .ok_or_else(|| format!("expected uniform:<min>-<max>, got '{}'", s))?;
+                let (min, max) = (parse_size(min)?, parse_size(max)?);
+                if min > max {
+                    return Err(format!("min {} is larger than max {}", min, max));
+                }
+                Ok(SizeDistribution::Uniform(min, max))
+            }
+            "choice" => {
+                let sizes = params
+                    .split(',')
+                    .map(parse_size)
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(SizeDistribution::Choice(sizes))
+            }
+            _ => Err(format!(
+                "unknown size distribution '{}', expected fixed, uniform or choice",
+                kind
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SizeDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SizeDistribution::Fixed(size) => write!(f, "fixed:{}", size),
+            SizeDistribution::Uniform(min, max) => write!(f, "uniform:{}-{}", min, max),
+            SizeDistribution::Choice(sizes) => {
+                let sizes = sizes.iter().map(u64::to_string).collect::<Vec<_>>();
+                write!(f, "choice:{}", sizes.join(","))
+            }
+        }
+    }
+}
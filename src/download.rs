@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use futures::StreamExt;
+use object_store::{path::Path, ObjectStore, PutPayload};
+use serde::Deserialize;
+
+use crate::{
+    deadline::DeadlineArgs,
+    hedge::Hedger,
+    limiter::{run_limited_by, ConcurrencyLimiter},
+    multipart::{run_or_abort, SharedUpload, UploadError},
+    phase::PhaseOutcome,
+    stats::LatencySummary,
+};
+
+/// Options for uploading one large object and downloading it in ranges from several clients
+#[derive(clap::Args, Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DownloadArgs {
+    /// Size of each part of the upload, defaults to 8 MiB
+    #[arg(short, long)]
+    pub upload_size: Option<u64>,
+
+    /// Size of each ranged read, defaults to 32 MiB
+    #[arg(short, long)]
+    pub download_size: Option<u64>,
+
+    /// Size of the object, defaults to 1 GiB
+    #[arg(short, long)]
+    pub total_size: Option<u64>,
+
+    /// Number of clients, each with its own connection pool, defaults to 8
+    #[arg(short, long)]
+    pub num_clients: Option<u32>,
+
+    /// Reads in flight per client, defaults to 8
+    #[arg(short, long)]
+    pub max_threads_per_client: Option<u32>,
+}
+
+impl DownloadArgs {
+    /// The object at `path` and how to download it
+    pub fn download(&self, path: Path) -> Download {
+        Download {
+            path,
+            total_size: self.total_size.unwrap_or(1024 * 1024 * 1024),
+            upload_size: self.upload_size.unwrap_or(8 * 1024 * 1024),
+            download_size: self.download_size.unwrap_or(32 * 1024 * 1024),
+            num_clients: self.num_clients.unwrap_or(8),
+            threads_per_client: self.max_threads_per_client.unwrap_or(8),
+        }
+    }
+}
+
+/// The ranges of one object to download
+#[derive(Debug, Clone)]
+pub struct Download {
+    pub path: Path,
+    pub total_size: u64,
+    pub upload_size: u64,
+    pub download_size: u64,
+    pub num_clients: u32,
+    pub threads_per_client: u32,
+}
+
+impl Download {
+    /// Uploads `total_size` bytes to the object in `upload_size` parts, returning how many
+    /// bytes were written
+    pub async fn upload(&self, store: &dyn ObjectStore) -> Result<u64, UploadError> {
+        let mut bytes_written = 0;
+        println!(
+            "Uploading {} bytes of data in chunks of {}",
+            self.total_size, self.upload_size
+        );
+        // Just initialize whatever garbage
+        let mut data = bytes::BytesMut::with_capacity(self.upload_size as usize);
+        unsafe { data.set_len(self.upload_size as usize) };
+        let data = data.freeze();
+        let multipart = SharedUpload::new(store.put_multipart(&self.path).await?);
+        let total_start = std::time::Instant::now();
+        let upload = async {
+            while bytes_written < self.total_size {
+                let start = std::time::Instant::now();
+                log::debug!("About to upload {} bytes of data", data.len());
+                multipart
+                    .put_part(PutPayload::from_bytes(data.clone()))
+                    .await?;
+                log::debug!("Upload took {:?} seconds", start.elapsed().as_secs_f64());
+                bytes_written += self.upload_size;
+            }
+            multipart.complete().await
+        };
+        run_or_abort(&multipart, upload).await?;
+        println!(
+            "Total upload took {:?} seconds",
+            total_start.elapsed().as_secs_f64()
+        );
+        Ok(bytes_written)
+    }
+}
+
+/// Downloads every range of the object, spread round robin over the clients.  With a `limiter`
+/// it decides how many reads are in flight across all the clients instead of
+/// `threads_per_client`.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_iteration(
+    make_store: &impl Fn() -> Arc<dyn ObjectStore>,
+    download: &Download,
+    threads_per_client: usize,
+    include_failed_latencies: bool,
+    deadlines: &DeadlineArgs,
+    hedger: Option<&Arc<Hedger>>,
+    limiter: Option<&mut ConcurrencyLimiter>,
+) -> (PhaseOutcome, Option<LatencySummary>) {
+    let num_clients = download.num_clients;
+    let download_size = download.download_size;
+    let mut task_idx = 0;
+    let stores = (0..num_clients).map(|_| make_store()).collect::<Vec<_>>();
+    let mut read_tasks = Vec::new();
+    while (task_idx * download_size) < download.total_size {
+        let client_idx = (task_idx % num_clients as u64) as usize;
+        let path = download.path.clone();
+        let read_start = task_idx * download_size;
+        let read_end = read_start + download_size;
+        let store = stores[client_idx].clone();
+        read_tasks.push((client_idx, async move {
+            let start = std::time::Instant::now();
+            let range = read_start as usize..read_end as usize;
+            let read = async {
+                match hedger {
+                    Some(hedger) => hedger.get_range(&store, &path, range).await,
+                    None => store.get_range(&path, range).await,
+                }
+            };
+            let result = deadlines
+                .limit(read)
+                .await
+                .map(|result| result.map(|bytes| bytes.len() as u64));
+            let latency = start.elapsed().as_secs_f64();
+            // One line per request would scroll the dashboard away, so only log them
+            match &result {
+                Some(Ok(_)) => log::debug!(
+                    "Download on client {} took {:?} seconds",
+                    client_idx,
+                    latency
+                ),
+                Some(Err(e)) => log::debug!(
+                    "Download on client {} failed after {:?} seconds: {}",
+                    client_idx,
+                    latency,
+                    e
+                ),
+                None => log::debug!(
+                    "Download on client {} timed out after {:?} seconds",
+                    client_idx,
+                    latency
+                ),
+            }
+            (latency, result)
+        }));
+        task_idx += 1;
+    }
+
+    let total_start = std::time::Instant::now();
+    let downloads = match limiter {
+        Some(limiter) => run_limited_by(
+            read_tasks.into_iter().map(|(_, task)| task),
+            limiter,
+            |(_, result)| matches!(result, Some(Ok(_))),
+        )
+        .map(|(_, download)| download)
+        .boxed_local(),
+        None => {
+            let mut per_client = stores.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+            for (client_idx, task) in read_tasks {
+                per_client[client_idx].push(task);
+            }
+            futures::stream::select_all(
+                per_client
+                    .into_iter()
+                    .map(|tasks| futures::stream::iter(tasks).buffer_unordered(threads_per_client)),
+            )
+            .boxed_local()
+        }
+    };
+    let mut outcome = PhaseOutcome::default();
+    let mut num_finished = 0;
+    let cut_short = deadlines
+        .drain(downloads, total_start, |(latency, result)| {
+            num_finished += 1;
+            match result {
+                Some(result) => outcome.record(latency, &result),
+                None => outcome.timed_out += 1,
+            }
+        })
+        .await;
+    outcome.elapsed_secs = total_start.elapsed().as_secs_f64();
+    if cut_short {
+        outcome.cancelled = task_idx - num_finished;
+    }
+    if let Some(hedger) = hedger {
+        hedger.finish_stragglers().await;
+    }
+    if include_failed_latencies {
+        outcome.include_failed_latencies();
+    }
+    let latency = LatencySummary::from_samples(&mut outcome.latencies);
+    (outcome, latency)
+}
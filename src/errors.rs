@@ -0,0 +1,263 @@
+use std::{collections::BTreeMap, error::Error, fmt};
+
+use crate::results::RunResults;
+
+/// Coarse classification of store errors for reporting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ErrorClass {
+    NotFound,
+    AlreadyExists,
+    Precondition,
+    NotModified,
+    NotSupported,
+    /// Any other HTTP error response, e.g. 503 slow down
+    Status(u16),
+    Timeout,
+    /// Couldn't connect to the store at all
+    Connect,
+    Other,
+}
+
+impl ErrorClass {
+    pub fn of(error: &object_store::Error) -> Self {
+        match error {
+            object_store::Error::NotFound { .. } => ErrorClass::NotFound,
+            object_store::Error::AlreadyExists { .. } => ErrorClass::AlreadyExists,
+            object_store::Error::Precondition { .. } => ErrorClass::Precondition,
+            object_store::Error::NotModified { .. } => ErrorClass::NotModified,
+            object_store::Error::NotSupported { .. } | object_store::Error::NotImplemented => {
+                ErrorClass::NotSupported
+            }
+            object_store::Error::Generic { source, .. } => Self::of_source(source.as_ref()),
+            _ => ErrorClass::Other,
+        }
+    }
+
+    /// Looks through the chain of causes for the HTTP or IO error underneath
+    fn of_source(error: &(dyn Error + 'static)) -> Self {
+        let mut cause = Some(error);
+        while let Some(error) = cause {
+            if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+                if let Some(status) = error.status() {
+                    return ErrorClass::Status(status.as_u16());
+                } else if error.is_timeout() {
+                    return ErrorClass::Timeout;
+                } else if error.is_connect() {
+                    return ErrorClass::Connect;
+                }
+            }
+            if let Some(error) = error.downcast_ref::<std::io::Error>() {
+                match error.kind() {
+                    std::io::ErrorKind::TimedOut => return ErrorClass::Timeout,
+                    std::io::ErrorKind::ConnectionRefused => return ErrorClass::Connect,
+                    _ => {}
+                }
+            }
+            // Responses that aren't retried don't keep the reqwest error, only its status
+            if let Some(status) = error
+                .to_string()
+                .strip_prefix("Client error with status ")
+                .and_then(|rest| rest.get(..3))
+                .and_then(|status| status.parse().ok())
+            {
+                return ErrorClass::Status(status);
+            }
+            cause = error.source();
+        }
+        ErrorClass::Other
+    }
+
+    /// Whether the store was asking us to slow down
+    pub fn is_throttling(&self) -> bool {
+        matches!(self, ErrorClass::Status(429 | 503))
+    }
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorClass::NotFound => "not_found",
+            ErrorClass::AlreadyExists => "already_exists",
+            ErrorClass::Precondition => "precondition",
+            ErrorClass::NotModified => "not_modified",
+            ErrorClass::NotSupported => "not_supported",
+            ErrorClass::Status(status) => return write!(f, "http_{}", status),
+            ErrorClass::Timeout => "timeout",
+            ErrorClass::Connect => "connect",
+            ErrorClass::Other => "other",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Number of errors seen of each class
+#[derive(Debug, Clone, Default)]
+pub struct ErrorCounts {
+    counts: BTreeMap<ErrorClass, u64>,
+}
+
+impl ErrorCounts {
+    pub fn record(&mut self, error: &object_store::Error) {
+        *self.counts.entry(ErrorClass::of(error)).or_default() += 1;
+    }
+
+    pub fn merge(&mut self, other: &ErrorCounts) {
+        for (class, count) in &other.counts {
+            *self.counts.entry(*class).or_default() += count;
+        }
+    }
+
+    pub fn get(&self, class: ErrorClass) -> u64 {
+        self.counts.get(&class).copied().unwrap_or(0)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// Errors where the store was asking us to slow down
+    pub fn throttled(&self) -> u64 {
+        self.counts
+            .iter()
+            .filter(|(class, _)| class.is_throttling())
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// ` errors: ...` to append to a summary line, or nothing if there were no errors
+    pub fn suffix(&self) -> String {
+        if self.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", self)
+        }
+    }
+
+    /// Counts keyed by class name, for results files
+    pub fn to_map(&self) -> BTreeMap<String, u64> {
+        self.counts
+            .iter()
+            .map(|(class, count)| (class.to_string(), *count))
+            .collect()
+    }
+}
+
+impl fmt::Display for ErrorCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = self
+            .counts
+            .iter()
+            .map(|(class, count)| format!("{}={}", class, count))
+            .collect::<Vec<_>>();
+        if counts.is_empty() {
+            write!(f, "errors: none")
+        } else if self.throttled() > 0 {
+            write!(
+                f,
+                "errors: {} ({} throttled)",
+                counts.join(" "),
+                self.throttled()
+            )
+        } else {
+            write!(f, "errors: {}", counts.join(" "))
+        }
+    }
+}
+
+/// Options for how a workload handles failed requests
+#[derive(clap::Args, Clone, Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ErrorArgs {
+    /// Count failed requests and carry on, instead of stopping after the iteration in which the
+    /// first one fails
+    #[arg(long)]
+    pub continue_on_error: bool,
+
+    /// Include the latency of failed requests in the latency percentiles
+    #[arg(long)]
+    pub include_failed_latencies: bool,
+
+    /// With --continue-on-error, stop once more than this fraction of an iteration's requests
+    /// fail, defaults to 0.1
+    #[arg(long)]
+    pub max_error_rate: Option<f64>,
+}
+
+impl ErrorArgs {
+    /// Whether to stop the run after an iteration that saw `errors` out of `num_requests`.
+    /// Prints the reason and records it in `results` if so.
+    pub fn should_stop(
+        &self,
+        errors: &ErrorCounts,
+        num_requests: u64,
+        results: &mut RunResults,
+    ) -> bool {
+        if errors.is_empty() {
+            return false;
+        }
+        let error_rate = errors.total() as f64 / num_requests.max(1) as f64;
+        let max_error_rate = self.max_error_rate.unwrap_or(0.1);
+        let reason = if !self.continue_on_error {
+            format!("requests failed ({})", errors)
+        } else if error_rate > max_error_rate {
+            format!(
+                "{:.1}% of requests failed ({}), more than the maximum of {:.1}%",
+                error_rate * 100.0,
+                errors,
+                max_error_rate * 100.0
+            )
+        } else {
+            return false;
+        };
+        eprintln!("Stopping the run early: {}", reason);
+        results
+            .summary
+            .insert("stopped_early".to_string(), reason.into());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    use object_store::{aws::AmazonS3Builder, path::Path, ObjectStore};
+
+    use super::*;
+
+    /// object_store doesn't expose the error it returns for responses it doesn't retry, so
+    /// `of_source` parses the status out of its message.  Catch an upgrade changing it.
+    #[tokio::test]
+    async fn status_of_error_that_was_not_retried() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let _ = stream.read(&mut [0; 4096]);
+                let _ = stream.write_all(
+                    b"HTTP/1.1 403 Forbidden\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                );
+            }
+        });
+        let store = AmazonS3Builder::new()
+            .with_endpoint(format!("http://{}", addr))
+            .with_allow_http(true)
+            .with_bucket_name("bucket")
+            .with_region("us-east-1")
+            .with_access_key_id("key")
+            .with_secret_access_key("secret")
+            .build()
+            .unwrap();
+
+        let error = store.head(&Path::from("object")).await.unwrap_err();
+        assert_eq!(ErrorClass::of(&error), ErrorClass::Status(403), "{}", error);
+    }
+}
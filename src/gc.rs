@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use clap::Parser;
+use object_store_bench::{
+    namespace::{bench_root, delete_prefix, run_age},
+    store::StoreArgs,
+};
+
+/// Deletes the namespaces of old benchmark runs
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    store: StoreArgs,
+
+    /// Only delete runs started at least this many hours ago
+    #[arg(long)]
+    max_age_hours: Option<u64>,
+
+    /// List the runs that would be deleted without deleting them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let args = Args::parse();
+
+    let max_age = Duration::from_secs(args.max_age_hours.unwrap_or(24) * 60 * 60);
+
+    let (store, base_path) = args.store.build();
+    let root = bench_root(&base_path);
+
+    let listing = match store.list_with_delimiter(Some(&root)).await {
+        Ok(listing) => listing,
+        Err(e) => {
+            eprintln!("Listing {} failed: {}", root, e);
+            std::process::exit(1);
+        }
+    };
+    let mut num_runs = 0;
+    let mut num_objects = 0;
+    for run_prefix in listing.common_prefixes {
+        let Some(run_id) = run_prefix.filename() else {
+            continue;
+        };
+        let Some(age) = run_age(run_id) else {
+            log::warn!("Skipping {} which does not look like a run id", run_prefix);
+            continue;
+        };
+        if age < max_age {
+            continue;
+        }
+        println!("Run {} is {} hours old", run_id, age.as_secs() / (60 * 60));
+        if args.dry_run {
+            continue;
+        }
+        let num_deleted = match delete_prefix(store.as_ref(), &run_prefix).await {
+            Ok(num_deleted) => num_deleted,
+            Err(e) => {
+                eprintln!("Deleting {} failed: {}", run_prefix, e);
+                std::process::exit(1);
+            }
+        };
+        println!("Deleted {} objects under {}", num_deleted, run_prefix);
+        num_runs += 1;
+        num_objects += num_deleted;
+    }
+
+    println!("Deleted {} runs ({} objects)", num_runs, num_objects);
+}
@@ -0,0 +1,260 @@
+use std::time::Instant;
+
+use clap::{Parser, ValueEnum};
+use futures::StreamExt;
+use object_store::{GetOptions, ObjectStore, PutPayload};
+use object_store_bench::{
+    args::parse_fraction,
+    data::random_bytes,
+    errors::{ErrorArgs, ErrorClass, ErrorCounts},
+    namespace::RunNamespace,
+    phase::run_phase,
+    results::{IterationResult, RunResults},
+    stats::{LatencyHistogram, LatencySummary},
+    store::StoreArgs,
+    timeline::TimelineArgs,
+    timing::TimingArgs,
+};
+use rand::Rng;
+
+/// Measures metadata requests (`head` and `get_opts` with `head: true`) across many objects
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    store: StoreArgs,
+
+    #[command(flatten)]
+    timeline: TimelineArgs,
+
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    num_objects: Option<u64>,
+
+    #[arg(long)]
+    object_size: Option<u64>,
+
+    /// Concurrency levels to measure at
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    concurrency: Vec<usize>,
+
+    #[arg(long)]
+    requests_per_level: Option<u64>,
+
+    /// Fraction of requests that target objects which don't exist
+    #[arg(long, value_parser = parse_fraction)]
+    missing_fraction: Option<f64>,
+
+    #[arg(long, value_enum, default_value_t = HeadMethod::Both)]
+    method: HeadMethod,
+
+    #[arg(long)]
+    skip_upload: bool,
+
+    /// How many times to measure every method at every concurrency level, defaults to 1
+    #[arg(long)]
+    num_iterations: Option<u32>,
+
+    #[command(flatten)]
+    timing: TimingArgs,
+
+    #[command(flatten)]
+    errors: ErrorArgs,
+
+    /// Reuse the namespace of an earlier run instead of creating a new one
+    #[arg(long)]
+    run_id: Option<String>,
+
+    /// Delete everything written under the run's namespace once the run finishes
+    #[arg(long)]
+    cleanup: bool,
+
+    /// Write machine readable results to this file
+    #[arg(long)]
+    results: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum HeadMethod {
+    Head,
+    GetOpts,
+    Both,
+}
+
+async fn head_once(
+    store: &dyn ObjectStore,
+    method: HeadMethod,
+    path: &object_store::path::Path,
+) -> object_store::Result<()> {
+    match method {
+        HeadMethod::GetOpts => {
+            let options = GetOptions {
+                head: true,
+                ..Default::default()
+            };
+            store.get_opts(path, options).await.map(|_| ())
+        }
+        _ => store.head(path).await.map(|_| ()),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    object_store_bench::retries::init_logging();
+
+    let args = Args::parse();
+
+    let num_objects = args.num_objects.unwrap_or(1000);
+    let object_size = args.object_size.unwrap_or(1024);
+    let requests_per_level = args.requests_per_level.unwrap_or(10000);
+    let missing_fraction = args.missing_fraction.unwrap_or(0.0);
+    let num_iterations = args.num_iterations.unwrap_or(1);
+    let concurrency_levels = if args.concurrency.is_empty() {
+        vec![1, 8, 64, 256]
+    } else {
+        args.concurrency
+    };
+    let methods = match args.method {
+        HeadMethod::Both => vec![HeadMethod::Head, HeadMethod::GetOpts],
+        method => vec![method],
+    };
+
+    if args.skip_upload && args.run_id.is_none() {
+        eprintln!("--skip-upload requires the --run-id of the run that uploaded the data");
+        std::process::exit(1);
+    }
+
+    let (store, base_path) = args.store.build();
+    let timeline = args.timeline.start();
+    let store = timeline.wrap(store);
+    let namespace = RunNamespace::new(&base_path, args.run_id);
+    println!("Run id: {}", namespace.run_id);
+
+    let mut results = RunResults::new("head", &namespace.run_id, namespace.prefix.as_ref());
+    args.store.record(&mut results);
+    let mut stopped_early = false;
+    if !args.skip_upload {
+        println!("Writing {} objects of {} bytes", num_objects, object_size);
+        let data = random_bytes(object_size);
+        let populate = run_phase(num_objects, 64, |idx| {
+            let store = store.clone();
+            let path = namespace.path(&format!("head/{}", idx));
+            let payload = PutPayload::from_bytes(data.clone());
+            async move { store.put(&path, payload).await.map(|_| object_size) }
+        })
+        .await;
+        stopped_early =
+            args.errors
+                .should_stop(&populate.errors, populate.num_requests(), &mut results);
+    }
+
+    let mut schedule = args.timing.schedule(num_iterations);
+    while !stopped_early && schedule.next_iteration() {
+        let mut iteration = Vec::new();
+        // Not found is expected of the objects that are meant to be missing
+        let mut unexpected_errors = ErrorCounts::default();
+        let mut num_requests = 0;
+        for &method in &methods {
+            for &concurrency in &concurrency_levels {
+                let mut rng = rand::thread_rng();
+                let tasks = (0..requests_per_level)
+                    .map(|_| {
+                        let idx = rng.gen_range(0..num_objects);
+                        let missing = rng.gen_bool(missing_fraction);
+                        let name = if missing {
+                            format!("head/missing-{}", idx)
+                        } else {
+                            format!("head/{}", idx)
+                        };
+                        let store = store.clone();
+                        let path = namespace.path(&name);
+                        async move {
+                            let start = Instant::now();
+                            let result = head_once(store.as_ref(), method, &path).await;
+                            (start.elapsed().as_secs_f64(), missing, result)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                let total_start = Instant::now();
+                let outcomes = futures::stream::iter(tasks)
+                    .buffer_unordered(concurrency)
+                    .collect::<Vec<_>>()
+                    .await;
+                let elapsed = total_start.elapsed().as_secs_f64();
+
+                let mut latencies = Vec::with_capacity(outcomes.len());
+                let mut errors = ErrorCounts::default();
+                for (latency, missing, result) in outcomes {
+                    let Err(e) = result else {
+                        latencies.push(latency);
+                        continue;
+                    };
+                    errors.record(&e);
+                    let class = ErrorClass::of(&e);
+                    if missing && class == ErrorClass::NotFound {
+                        latencies.push(latency);
+                        continue;
+                    }
+                    if class == ErrorClass::Other {
+                        log::warn!("Unexpected error from {:?}: {}", method, e);
+                    }
+                    unexpected_errors.record(&e);
+                    if args.errors.include_failed_latencies {
+                        latencies.push(latency);
+                    }
+                }
+                num_requests += requests_per_level;
+                let histogram = LatencyHistogram::from_samples(&latencies);
+                let latency = LatencySummary::from_samples(&mut latencies);
+                println!(
+                    "Total {:?} at concurrency {} took {:?} seconds ({} ops/s {} {})",
+                    method,
+                    concurrency,
+                    elapsed,
+                    requests_per_level as f64 / elapsed,
+                    latency
+                        .as_ref()
+                        .map(ToString::to_string)
+                        .unwrap_or_default(),
+                    errors
+                );
+                print!("{}", histogram);
+
+                iteration.push(IterationResult {
+                    label: Some(format!("{:?}", method)),
+                    object_size: Some(object_size),
+                    concurrency: Some(concurrency),
+                    elapsed_secs: elapsed,
+                    num_requests: requests_per_level,
+                    latency,
+                    histogram: Some(histogram),
+                    errors: errors.to_map(),
+                    ..Default::default()
+                });
+            }
+        }
+        stopped_early = args
+            .errors
+            .should_stop(&unexpected_errors, num_requests, &mut results);
+        schedule.record(iteration, &mut results);
+    }
+    schedule.finish(&mut results);
+
+    timeline.finish(&mut results);
+    if let Some(results_path) = args.results {
+        results.write(results_path);
+    }
+
+    if args.cleanup {
+        let num_deleted = namespace.cleanup(store.as_ref()).await;
+        println!("Deleted {} objects under {}", num_deleted, namespace.prefix);
+    }
+
+    if stopped_early {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,328 @@
+use std::{
+    fmt,
+    ops::Range,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use object_store::{path::Path, ObjectStore, Result};
+use serde::Deserialize;
+use tokio::task::JoinHandle;
+
+use crate::{results::RunResults, stats::LatencySummary};
+
+/// Reads to see before hedging at a percentile of their latency
+const MIN_SAMPLES: usize = 20;
+
+/// Recompute the percentile delay once this many more reads have finished
+const RECOMPUTE_EVERY: usize = 100;
+
+/// Only the most recent reads count towards the percentile delay
+const WINDOW: usize = 1000;
+
+/// How long to wait for a read before sending a duplicate, parsed from the command line
+///
+/// * `<seconds>`, e.g. `0.05`, waits a fixed time
+/// * `p<percentile>`, e.g. `p95`, waits as long as that percentile of the recent reads took
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum HedgeDelay {
+    Fixed(f64),
+    Percentile(f64),
+}
+
+impl FromStr for HedgeDelay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix('p') {
+            Some(pct) => {
+                let pct = pct
+                    .parse::<f64>()
+                    .map_err(|e| format!("invalid percentile '{}': {}", pct, e))?;
+                if !(pct > 0.0 && pct < 100.0) {
+                    return Err(format!("percentile must be between 0 and 100, got {}", pct));
+                }
+                Ok(HedgeDelay::Percentile(pct))
+            }
+            None => {
+                let secs = s
+                    .parse::<f64>()
+                    .map_err(|e| format!("invalid delay '{}': {}", s, e))?;
+                if !(secs >= 0.0 && secs.is_finite()) {
+                    return Err(format!("delay must not be negative, got {}", secs));
+                }
+                Ok(HedgeDelay::Fixed(secs))
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for HedgeDelay {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for HedgeDelay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HedgeDelay::Fixed(secs) => write!(f, "{}", secs),
+            HedgeDelay::Percentile(pct) => write!(f, "p{}", pct),
+        }
+    }
+}
+
+/// Options for hedging ranged reads
+#[derive(clap::Args, Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HedgeArgs {
+    /// Send a duplicate of a read that hasn't finished after this many seconds, or `p<N>` for
+    /// the Nth percentile of recent reads, and use whichever response arrives first
+    #[arg(long)]
+    pub hedge_after: Option<HedgeDelay>,
+}
+
+impl HedgeArgs {
+    pub fn hedger(&self) -> Option<Arc<Hedger>> {
+        self.hedge_after.map(|delay| Arc::new(Hedger::new(delay)))
+    }
+}
+
+/// Issues hedged reads and keeps track of what they cost and saved.
+///
+/// When the duplicate wins, the original read is left to finish in the background instead of
+/// being cancelled, so that the latency it would have had can be compared against.  Call
+/// [`Hedger::finish_stragglers`] before starting the next iteration so those don't overlap it.
+#[derive(Debug)]
+pub struct Hedger {
+    delay: HedgeDelay,
+    /// The percentile delay in microseconds, 0 until enough reads have finished
+    percentile_delay_micros: AtomicU64,
+    num_reads: AtomicU64,
+    num_hedges: AtomicU64,
+    num_hedge_wins: AtomicU64,
+    /// What each read took with hedging, and what its original request took
+    hedged: Mutex<Vec<f64>>,
+    unhedged: Mutex<Vec<f64>>,
+    /// Original reads still running after their duplicate won
+    stragglers: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Hedger {
+    pub fn new(delay: HedgeDelay) -> Self {
+        Self {
+            delay,
+            percentile_delay_micros: AtomicU64::new(0),
+            num_reads: AtomicU64::new(0),
+            num_hedges: AtomicU64::new(0),
+            num_hedge_wins: AtomicU64::new(0),
+            hedged: Mutex::default(),
+            unhedged: Mutex::default(),
+            stragglers: Mutex::default(),
+        }
+    }
+
+    /// How long to wait before hedging, `None` while there isn't enough history to tell
+    fn delay(&self) -> Option<Duration> {
+        match self.delay {
+            HedgeDelay::Fixed(secs) => Some(Duration::from_secs_f64(secs)),
+            HedgeDelay::Percentile(_) => {
+                match self.percentile_delay_micros.load(Ordering::Relaxed) {
+                    0 => None,
+                    micros => Some(Duration::from_micros(micros)),
+                }
+            }
+        }
+    }
+
+    fn record_unhedged(&self, latency: f64) {
+        let mut unhedged = self.unhedged.lock().unwrap();
+        unhedged.push(latency);
+        if let HedgeDelay::Percentile(pct) = self.delay {
+            let len = unhedged.len();
+            if len == MIN_SAMPLES || (len > MIN_SAMPLES && len.is_multiple_of(RECOMPUTE_EVERY)) {
+                let mut recent = unhedged[unhedged.len().saturating_sub(WINDOW)..].to_vec();
+                recent.sort_by(f64::total_cmp);
+                let delay = crate::stats::percentile(&recent, pct);
+                self.percentile_delay_micros
+                    .store(((delay * 1e6) as u64).max(1), Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Waits for the original reads that lost to their duplicate to finish, so their latency is
+    /// recorded
+    pub async fn finish_stragglers(&self) {
+        let stragglers = std::mem::take(&mut *self.stragglers.lock().unwrap());
+        for straggler in stragglers {
+            let _ = straggler.await;
+        }
+    }
+
+    /// Reads `range` of `location`, sending a second request if the first one takes longer
+    /// than the hedge delay.  A duplicate that fails doesn't win, the original is waited for
+    /// instead.
+    pub async fn get_range(
+        self: &Arc<Self>,
+        store: &Arc<dyn ObjectStore>,
+        location: &Path,
+        range: Range<usize>,
+    ) -> Result<Bytes> {
+        let start = Instant::now();
+        self.num_reads.fetch_add(1, Ordering::Relaxed);
+        let mut pending = Pending {
+            hedger: self,
+            start,
+            done: false,
+        };
+        let mut primary = Box::pin({
+            let store = store.clone();
+            let location = location.clone();
+            let range = range.clone();
+            async move { store.get_range(&location, range).await }
+        });
+        let result = match self.delay() {
+            None => primary.await,
+            Some(delay) => match tokio::time::timeout(delay, &mut primary).await {
+                Ok(result) => result,
+                Err(_) => {
+                    self.num_hedges.fetch_add(1, Ordering::Relaxed);
+                    let hedge = store.get_range(location, range);
+                    tokio::select! {
+                        result = &mut primary => result,
+                        Ok(bytes) = hedge => {
+                            pending.done = true;
+                            self.num_hedge_wins.fetch_add(1, Ordering::Relaxed);
+                            self.hedged
+                                .lock()
+                                .unwrap()
+                                .push(start.elapsed().as_secs_f64());
+                            let hedger = self.clone();
+                            let straggler = tokio::spawn(async move {
+                                let _ = primary.await;
+                                hedger.record_unhedged(start.elapsed().as_secs_f64());
+                            });
+                            self.stragglers.lock().unwrap().push(straggler);
+                            return Ok(bytes);
+                        }
+                    }
+                }
+            },
+        };
+        pending.done = true;
+        let latency = start.elapsed().as_secs_f64();
+        self.hedged.lock().unwrap().push(latency);
+        self.record_unhedged(latency);
+        result
+    }
+
+    /// Prints what hedging cost and how it changed the tail latency, and records it in the
+    /// run's summary
+    pub async fn report(&self, results: &mut RunResults) {
+        self.finish_stragglers().await;
+        let num_reads = self.num_reads.load(Ordering::Relaxed);
+        let num_hedges = self.num_hedges.load(Ordering::Relaxed);
+        let num_hedge_wins = self.num_hedge_wins.load(Ordering::Relaxed);
+        let hedged = LatencySummary::from_samples(&mut self.hedged.lock().unwrap());
+        let unhedged = LatencySummary::from_samples(&mut self.unhedged.lock().unwrap());
+        let extra_requests = num_hedges as f64 / num_reads.max(1) as f64;
+        println!(
+            "Hedging after {}: {} duplicate requests for {} reads ({:.1}% extra), {} won",
+            self.delay,
+            num_hedges,
+            num_reads,
+            extra_requests * 100.0,
+            num_hedge_wins
+        );
+        if let (Some(hedged), Some(unhedged)) = (&hedged, &unhedged) {
+            println!("With hedging: {}", hedged);
+            println!("Without: {}", unhedged);
+            println!(
+                "p90 improved by {:.1}%, p99 by {:.1}%",
+                (1.0 - hedged.p90 / unhedged.p90) * 100.0,
+                (1.0 - hedged.p99 / unhedged.p99) * 100.0
+            );
+        }
+        let mut hedging = serde_json::Map::new();
+        hedging.insert("hedge_after".to_string(), self.delay.to_string().into());
+        hedging.insert("num_reads".to_string(), num_reads.into());
+        hedging.insert("num_hedges".to_string(), num_hedges.into());
+        hedging.insert("num_hedge_wins".to_string(), num_hedge_wins.into());
+        hedging.insert("extra_requests".to_string(), extra_requests.into());
+        hedging.insert(
+            "hedged_latency".to_string(),
+            serde_json::to_value(&hedged).unwrap(),
+        );
+        hedging.insert(
+            "unhedged_latency".to_string(),
+            serde_json::to_value(&unhedged).unwrap(),
+        );
+        results
+            .summary
+            .insert("hedging".to_string(), hedging.into());
+    }
+}
+
+/// A read that hasn't finished yet.  If it is dropped first, e.g. by `--op-timeout`, the read
+/// is recorded as taking as long as it ran for, with and without hedging.
+struct Pending<'a> {
+    hedger: &'a Hedger,
+    start: Instant,
+    done: bool,
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            let latency = self.start.elapsed().as_secs_f64();
+            self.hedger.hedged.lock().unwrap().push(latency);
+            self.hedger.record_unhedged(latency);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let cases = [
+            ("0.05", Ok(HedgeDelay::Fixed(0.05))),
+            ("0", Ok(HedgeDelay::Fixed(0.0))),
+            ("2", Ok(HedgeDelay::Fixed(2.0))),
+            ("p95", Ok(HedgeDelay::Percentile(95.0))),
+            ("p99.9", Ok(HedgeDelay::Percentile(99.9))),
+            ("p", Err("invalid percentile")),
+            ("pfast", Err("invalid percentile")),
+            ("p0", Err("percentile must be between 0 and 100")),
+            ("p100", Err("percentile must be between 0 and 100")),
+            ("p-5", Err("percentile must be between 0 and 100")),
+            ("pNaN", Err("percentile must be between 0 and 100")),
+            ("", Err("invalid delay")),
+            ("50ms", Err("invalid delay")),
+            ("P95", Err("invalid delay")),
+            ("-0.1", Err("delay must not be negative")),
+            ("inf", Err("delay must not be negative")),
+            ("NaN", Err("delay must not be negative")),
+        ];
+        for (s, expected) in cases {
+            match (s.parse::<HedgeDelay>(), expected) {
+                (Ok(delay), Ok(expected)) => {
+                    assert_eq!(delay, expected, "{}", s);
+                    assert_eq!(delay.to_string().parse::<HedgeDelay>(), Ok(delay));
+                }
+                (Err(e), Err(expected)) => assert!(e.contains(expected), "{}: {}", s, e),
+                (result, _) => panic!("{}: unexpected {:?}", s, result),
+            }
+        }
+    }
+}
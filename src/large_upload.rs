@@ -0,0 +1,306 @@
+use std::sync::Arc;
+
+use futures::{StreamExt, TryStreamExt};
+use object_store::{buffered::BufWriter, path::Path, ObjectStore, PutPayload, WriteMultipart};
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    data::random_bytes,
+    limiter::{run_limited, ConcurrencyLimiter},
+    multipart::{abort_upload, run_or_abort, until_interrupted, SharedUpload, UploadError},
+};
+
+/// Options for uploading one large object
+#[derive(clap::Args, Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadArgs {
+    /// Size of the first part, defaults to 5 MiB
+    #[arg(short, long)]
+    pub initial_part_size: Option<u64>,
+
+    /// Size of the object, defaults to 2 TiB
+    #[arg(short, long)]
+    pub total_size: Option<u64>,
+
+    /// Parts in flight, defaults to 32
+    #[arg(short, long)]
+    pub max_parallelism: Option<u64>,
+
+    /// Which object_store API to drive the upload with
+    #[arg(long, value_enum, default_value_t = UploadMode::PutPart)]
+    pub mode: UploadMode,
+
+    /// Upload `--mode put-part` parts at the fixed size the other modes use instead of growing
+    /// them, so that the modes can be compared like for like
+    #[arg(long)]
+    pub fixed_part_size: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UploadMode {
+    /// Call `MultipartUpload::put_part` directly with growing part sizes, unless
+    /// `--fixed-part-size`
+    #[default]
+    PutPart,
+    /// Stream fixed size chunks through `WriteMultipart`, waiting for capacity between writes
+    WriteMultipart,
+    /// Write through `object_store::buffered::BufWriter`
+    BufWriter,
+}
+
+pub const PART_SIZE_INCREMENT: u64 = 5 * 1024 * 1024;
+
+/// Stores limit multipart uploads to this many parts
+const MAX_PARTS: u64 = 10000;
+
+/// Give up on completing the upload (and abort it) after this many attempts
+const MAX_COMPLETE_ATTEMPTS: u32 = 10;
+
+impl UploadArgs {
+    pub fn total_size(&self) -> u64 {
+        self.total_size.unwrap_or(2 * 1024 * 1024 * 1024 * 1024)
+    }
+
+    pub fn initial_part_size(&self) -> u64 {
+        self.initial_part_size.unwrap_or(PART_SIZE_INCREMENT)
+    }
+
+    pub fn max_parallelism(&self) -> u64 {
+        self.max_parallelism.unwrap_or(32)
+    }
+
+    /// The part size of the streaming writers (and put-part with `fixed_part_size`), grown if
+    /// needed to stay within the part limit
+    pub fn fixed_part_size(&self) -> u64 {
+        self.initial_part_size()
+            .max(self.total_size().div_ceil(MAX_PARTS))
+    }
+
+    /// The name of the mode, as given on the command line
+    pub fn mode_name(&self) -> Option<String> {
+        use clap::ValueEnum;
+        self.mode
+            .to_possible_value()
+            .map(|mode| mode.get_name().to_string())
+    }
+
+    /// Uploads `total_size` bytes to `path`.  Only `UploadMode::PutPart` uses the `limiter`, and
+    /// with one its parts are all the same size so their latencies are comparable.
+    pub async fn upload(
+        &self,
+        store: Arc<dyn ObjectStore>,
+        path: &Path,
+        limiter: Option<&mut ConcurrencyLimiter>,
+    ) -> Result<UploadStats, UploadError> {
+        let total_size = self.total_size();
+        let max_parallelism = self.max_parallelism();
+        let fixed_part_size = self.fixed_part_size();
+        match self.mode {
+            UploadMode::PutPart => {
+                upload_put_part(
+                    store.as_ref(),
+                    path,
+                    total_size,
+                    self.initial_part_size(),
+                    (self.fixed_part_size || limiter.is_some()).then_some(fixed_part_size),
+                    max_parallelism,
+                    limiter,
+                )
+                .await
+            }
+            UploadMode::WriteMultipart => {
+                upload_write_multipart(
+                    store.as_ref(),
+                    path,
+                    total_size,
+                    fixed_part_size,
+                    max_parallelism,
+                )
+                .await
+            }
+            UploadMode::BufWriter => {
+                upload_buf_writer(store, path, total_size, fixed_part_size, max_parallelism).await
+            }
+        }
+    }
+}
+
+pub struct UploadStats {
+    pub num_parts: u64,
+    pub bytes_written: u64,
+    pub min_part_size: u64,
+    pub max_part_size: u64,
+}
+
+impl UploadStats {
+    /// Stats of an upload in `part_size` parts, the last of which may be smaller
+    pub fn fixed(bytes_written: u64, part_size: u64) -> Self {
+        Self {
+            num_parts: bytes_written.div_ceil(part_size),
+            bytes_written,
+            min_part_size: match bytes_written % part_size {
+                0 => part_size,
+                last => last,
+            },
+            max_part_size: part_size,
+        }
+    }
+}
+
+async fn upload_put_part(
+    store: &dyn ObjectStore,
+    path: &Path,
+    total_size: u64,
+    initial_part_size: u64,
+    fixed_part_size: Option<u64>,
+    max_parallelism: u64,
+    limiter: Option<&mut ConcurrencyLimiter>,
+) -> Result<UploadStats, UploadError> {
+    let mut bytes_written = 0;
+
+    // Just initialize whatever garbage.  Max that `part_size` can reach is 99 * initial_part_size
+    // let max_part_size = 99 * initial_part_size as usize;
+    // let mut data = bytes::BytesMut::with_capacity(max_part_size);
+    // unsafe { data.set_len(max_part_size) };
+    // let data = data.freeze();
+
+    let multipart = SharedUpload::new(store.put_multipart(path).await?);
+
+    let mut tasks = Vec::with_capacity(10000);
+    let (mut min_part_size, mut max_part_size) = (u64::MAX, 0);
+    while bytes_written < total_size {
+        // let data = data.clone();
+        let multipart = multipart.clone();
+        let part_size = fixed_part_size.unwrap_or_else(|| {
+            initial_part_size.max(((tasks.len() as u64 / 100) + 1) * PART_SIZE_INCREMENT)
+        });
+        min_part_size = min_part_size.min(part_size);
+        max_part_size = max_part_size.max(part_size);
+        tasks.push(async move {
+            let start = std::time::Instant::now();
+            let part = random_bytes(part_size);
+            log::info!("About to upload {} bytes of data", part.len());
+            multipart.put_part(PutPayload::from_bytes(part)).await?;
+            log::info!(
+                "Upload took {:?} seconds progress={}",
+                start.elapsed().as_secs_f64(),
+                bytes_written as f64 / total_size as f64
+            );
+            Ok::<_, object_store::Error>(())
+        });
+        bytes_written += part_size;
+    }
+    log::info!("Generated {} tasks to upload", tasks.len());
+    let num_parts = tasks.len() as u64;
+    let upload = async {
+        match limiter {
+            Some(limiter) => {
+                run_limited(tasks, limiter)
+                    .map(|(_, result)| result)
+                    .try_collect::<Vec<_>>()
+                    .await?
+            }
+            None => {
+                futures::stream::iter(tasks)
+                    .buffered(max_parallelism as usize)
+                    .try_collect::<Vec<_>>()
+                    .await?
+            }
+        };
+
+        let mut attempt = 1;
+        loop {
+            match multipart.complete().await {
+                Ok(result) => break Ok(result),
+                Err(e) if attempt < MAX_COMPLETE_ATTEMPTS => {
+                    log::error!(
+                        "Error completing multipart upload (attempt {} of {}): {:?}",
+                        attempt,
+                        MAX_COMPLETE_ATTEMPTS,
+                        e
+                    );
+                    attempt += 1;
+                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                }
+                Err(e) => break Err(e),
+            }
+        }
+    };
+    run_or_abort(&multipart, upload).await?;
+    Ok(UploadStats {
+        num_parts,
+        bytes_written,
+        min_part_size,
+        max_part_size,
+    })
+}
+
+async fn upload_write_multipart(
+    store: &dyn ObjectStore,
+    path: &Path,
+    total_size: u64,
+    part_size: u64,
+    max_parallelism: u64,
+) -> Result<UploadStats, UploadError> {
+    let mut writer =
+        WriteMultipart::new_with_chunk_size(store.put_multipart(path).await?, part_size as usize);
+    let mut bytes_written = 0;
+    let write = async {
+        while bytes_written < total_size {
+            // Apply backpressure so at most `max_parallelism` parts are buffered in memory
+            writer.wait_for_capacity(max_parallelism as usize).await?;
+            let chunk = random_bytes(part_size.min(total_size - bytes_written));
+            let chunk_len = chunk.len();
+            bytes_written += chunk_len as u64;
+            writer.put(chunk);
+            log::info!(
+                "Buffered {} bytes progress={}",
+                chunk_len,
+                bytes_written as f64 / total_size as f64
+            );
+        }
+        // Wait for every full part to be uploaded while we can still abort on failure
+        writer.wait_for_capacity(0).await
+    };
+    if let Err(e) = until_interrupted(write).await {
+        abort_upload(&e, writer.abort()).await;
+        return Err(e);
+    }
+    // `finish` consumes the upload, so it can't be aborted if completing fails.  Everything
+    // but the final partial part has already been uploaded at this point though, and the
+    // `cleanup` command will find anything left behind.
+    writer.finish().await?;
+    Ok(UploadStats::fixed(bytes_written, part_size))
+}
+
+async fn upload_buf_writer(
+    store: Arc<dyn ObjectStore>,
+    path: &Path,
+    total_size: u64,
+    part_size: u64,
+    max_parallelism: u64,
+) -> Result<UploadStats, UploadError> {
+    let mut writer = BufWriter::with_capacity(store, path.clone(), part_size as usize)
+        .with_max_concurrency(max_parallelism as usize);
+    let mut bytes_written = 0;
+    let write = async {
+        while bytes_written < total_size {
+            let chunk = random_bytes(part_size.min(total_size - bytes_written));
+            writer.write_all(&chunk).await?;
+            bytes_written += chunk.len() as u64;
+            log::info!(
+                "Wrote {} bytes progress={}",
+                chunk.len(),
+                bytes_written as f64 / total_size as f64
+            );
+        }
+        writer.shutdown().await
+    };
+    if let Err(e) = until_interrupted(write).await {
+        abort_upload(&e, writer.abort()).await;
+        return Err(e);
+    }
+    Ok(UploadStats::fixed(bytes_written, part_size))
+}
@@ -0,0 +1,26 @@
+pub mod args;
+pub mod arrivals;
+pub mod client;
+pub mod dashboard;
+pub mod data;
+pub mod deadline;
+pub mod distribution;
+pub mod download;
+pub mod errors;
+pub mod hedge;
+pub mod large_upload;
+pub mod limiter;
+pub mod memory;
+pub mod mix;
+pub mod multipart;
+pub mod namespace;
+pub mod phase;
+pub mod random_reads;
+pub mod results;
+pub mod retries;
+pub mod scenario;
+pub mod stats;
+pub mod store;
+pub mod timeline;
+pub mod timing;
+pub mod tuning;
@@ -0,0 +1,375 @@
+use std::{future::Future, time::Instant};
+
+use futures::{stream::FuturesUnordered, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::results::RunResults;
+
+/// Limit the adaptive schedulers start from, unless given
+const DEFAULT_INITIAL_LIMIT: usize = 8;
+
+/// Windows are at least this many requests, so their average latency isn't just noise
+const MIN_WINDOW: usize = 16;
+
+/// AIMD backs off once a window's average latency exceeds this multiple of the best so far
+const AIMD_TOLERANCE: f64 = 2.0;
+
+/// AIMD multiplies the limit by this when backing off
+const AIMD_BACKOFF: f64 = 0.9;
+
+/// Weight of each window in the gradient scheduler's long-term latency average
+const GRADIENT_LONG_WEIGHT: f64 = 0.05;
+
+/// How far the gradient scheduler moves the limit towards its estimate each window
+const GRADIENT_SMOOTHING: f64 = 0.2;
+
+/// How a workload decides how many requests to keep in flight
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scheduler {
+    /// Always the configured concurrency
+    #[default]
+    Fixed,
+    /// Add one per window of requests, back off by 10% when latency doubles or requests fail
+    Aimd,
+    /// Scale by the ratio of long-term to recent latency, plus room to probe for more
+    Gradient,
+}
+
+/// Options for adapting the number of requests in flight
+#[derive(clap::Args, Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimiterArgs {
+    /// How to limit the requests in flight.  The adaptive schedulers never go above the
+    /// workload's configured concurrency.
+    #[arg(long, value_enum, default_value_t = Scheduler::Fixed)]
+    pub scheduler: Scheduler,
+
+    /// Limit the adaptive schedulers start from, defaults to 8
+    #[arg(long)]
+    pub initial_limit: Option<usize>,
+}
+
+impl LimiterArgs {
+    /// A limiter capped at `max_limit`, or `None` for the fixed scheduler
+    pub fn limiter(&self, max_limit: usize) -> Option<ConcurrencyLimiter> {
+        if self.scheduler == Scheduler::Fixed {
+            return None;
+        }
+        let initial_limit = self.initial_limit.unwrap_or(DEFAULT_INITIAL_LIMIT);
+        Some(ConcurrencyLimiter::new(
+            self.scheduler,
+            initial_limit,
+            max_limit,
+        ))
+    }
+}
+
+/// The limit after one window of requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LimitPoint {
+    /// Seconds since the limiter was created
+    pub t_secs: f64,
+    pub limit: usize,
+    /// Average latency of the window that led to this limit
+    pub avg_latency: f64,
+}
+
+/// Adjusts a concurrency limit from the latency and failures of each window of requests,
+/// where a window is as many requests as the limit (or [`MIN_WINDOW`], if more).
+#[derive(Debug)]
+pub struct ConcurrencyLimiter {
+    scheduler: Scheduler,
+    limit: f64,
+    max_limit: f64,
+    window: Vec<f64>,
+    window_errors: u64,
+    /// AIMD: lowest window average seen
+    min_latency: Option<f64>,
+    /// Gradient: moving average of the window averages
+    long_latency: Option<f64>,
+    start: Instant,
+    history: Vec<LimitPoint>,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(scheduler: Scheduler, initial_limit: usize, max_limit: usize) -> Self {
+        let max_limit = max_limit.max(1) as f64;
+        Self {
+            scheduler,
+            limit: (initial_limit as f64).clamp(1.0, max_limit),
+            max_limit,
+            window: Vec::new(),
+            window_errors: 0,
+            min_latency: None,
+            long_latency: None,
+            start: Instant::now(),
+            history: Vec::new(),
+        }
+    }
+
+    /// How many requests may be in flight right now
+    pub fn limit(&self) -> usize {
+        self.limit as usize
+    }
+
+    /// Feeds back the latency of a finished request and whether it succeeded
+    pub fn record(&mut self, latency: f64, succeeded: bool) {
+        self.window.push(latency);
+        if !succeeded {
+            self.window_errors += 1;
+        }
+        if self.window.len() < self.limit().max(MIN_WINDOW) {
+            return;
+        }
+        let avg_latency = self.window.iter().sum::<f64>() / self.window.len() as f64;
+        match self.scheduler {
+            Scheduler::Fixed => {}
+            Scheduler::Aimd => {
+                let min_latency = self.min_latency.map_or(avg_latency, |l| l.min(avg_latency));
+                self.min_latency = Some(min_latency);
+                if self.window_errors > 0 || avg_latency > min_latency * AIMD_TOLERANCE {
+                    self.limit *= AIMD_BACKOFF;
+                } else {
+                    self.limit += 1.0;
+                }
+            }
+            Scheduler::Gradient => {
+                let long_latency = self.long_latency.map_or(avg_latency, |l| {
+                    l * (1.0 - GRADIENT_LONG_WEIGHT) + avg_latency * GRADIENT_LONG_WEIGHT
+                });
+                self.long_latency = Some(long_latency);
+                let gradient = if self.window_errors > 0 {
+                    0.5
+                } else {
+                    (long_latency / avg_latency).clamp(0.5, 1.0)
+                };
+                let estimate = self.limit * gradient + self.limit.sqrt();
+                self.limit =
+                    self.limit * (1.0 - GRADIENT_SMOOTHING) + estimate * GRADIENT_SMOOTHING;
+            }
+        }
+        self.limit = self.limit.clamp(1.0, self.max_limit);
+        log::info!(
+            "Concurrency limit {} after a window averaging {:?} seconds",
+            self.limit(),
+            avg_latency
+        );
+        self.history.push(LimitPoint {
+            t_secs: self.start.elapsed().as_secs_f64(),
+            limit: self.limit(),
+            avg_latency,
+        });
+        self.window.clear();
+        self.window_errors = 0;
+    }
+
+    /// Prints how the limit moved and records its history in the run's summary
+    pub fn report(&self, results: &mut RunResults) {
+        let limits = self.history.iter().map(|point| point.limit);
+        match (limits.clone().min(), limits.clone().max()) {
+            (Some(min), Some(max)) => println!(
+                "Concurrency limit ({:?}): ended at {} after {} updates, between {} and {}, \
+                 averaging {}",
+                self.scheduler,
+                self.limit(),
+                self.history.len(),
+                min,
+                max,
+                limits.sum::<usize>() as f64 / self.history.len() as f64
+            ),
+            _ => println!(
+                "Concurrency limit ({:?}): never updated from {}",
+                self.scheduler,
+                self.limit()
+            ),
+        }
+        let mut limiter = serde_json::Map::new();
+        limiter.insert(
+            "scheduler".to_string(),
+            serde_json::to_value(self.scheduler).unwrap(),
+        );
+        limiter.insert("max_limit".to_string(), (self.max_limit as usize).into());
+        limiter.insert(
+            "history".to_string(),
+            serde_json::to_value(&self.history).unwrap(),
+        );
+        results
+            .summary
+            .insert("concurrency_limit".to_string(), limiter.into());
+    }
+}
+
+/// Runs `tasks`, starting the next whenever fewer than `limiter.limit()` are in flight, and
+/// yields the latency and result of each as it finishes
+pub fn run_limited<'a, Fut, T>(
+    tasks: impl IntoIterator<Item = Fut> + 'a,
+    limiter: &'a mut ConcurrencyLimiter,
+) -> impl Stream<Item = (f64, object_store::Result<T>)> + 'a
+where
+    Fut: Future<Output = object_store::Result<T>> + 'a,
+    T: 'a,
+{
+    run_limited_by(tasks, limiter, |result| result.is_ok())
+}
+
+/// Like [`run_limited`] for tasks with any output, `succeeded` telling the limiter which ones
+/// failed
+pub fn run_limited_by<'a, Fut>(
+    tasks: impl IntoIterator<Item = Fut> + 'a,
+    limiter: &'a mut ConcurrencyLimiter,
+    succeeded: impl Fn(&Fut::Output) -> bool + 'a,
+) -> impl Stream<Item = (f64, Fut::Output)> + 'a
+where
+    Fut: Future + 'a,
+{
+    let timed = |fut: Fut| async move {
+        let start = Instant::now();
+        let output = fut.await;
+        (start.elapsed().as_secs_f64(), output)
+    };
+    let in_flight = FuturesUnordered::new();
+    futures::stream::unfold(
+        (tasks.into_iter(), in_flight, limiter, succeeded),
+        move |(mut tasks, mut in_flight, limiter, succeeded)| async move {
+            while in_flight.len() < limiter.limit() {
+                match tasks.next() {
+                    Some(task) => in_flight.push(timed(task)),
+                    None => break,
+                }
+            }
+            let (latency, output) = in_flight.next().await?;
+            limiter.record(latency, succeeded(&output));
+            Some(((latency, output), (tasks, in_flight, limiter, succeeded)))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// Feeds one window of `limit().max(MIN_WINDOW)` requests with the same latency, `errors`
+    /// of them failed
+    fn window(limiter: &mut ConcurrencyLimiter, latency: f64, errors: usize) {
+        let size = limiter.limit().max(MIN_WINDOW);
+        for idx in 0..size {
+            limiter.record(latency, idx >= errors);
+        }
+    }
+
+    #[test]
+    fn waits_for_a_full_window() {
+        let mut limiter = ConcurrencyLimiter::new(Scheduler::Aimd, 8, 100);
+        for _ in 0..MIN_WINDOW - 1 {
+            limiter.record(0.01, true);
+        }
+        assert_eq!(limiter.limit(), 8);
+        assert!(limiter.history.is_empty());
+        limiter.record(0.01, true);
+        assert_eq!(limiter.limit(), 9);
+        assert_eq!(limiter.history.len(), 1);
+    }
+
+    #[test]
+    fn aimd_adds_one_and_backs_off() {
+        let mut limiter = ConcurrencyLimiter::new(Scheduler::Aimd, 8, 100);
+        let mut limits = Vec::new();
+        for _ in 0..3 {
+            window(&mut limiter, 0.01, 0);
+            limits.push(limiter.limit());
+        }
+        // A failure backs off by 10%, 11 -> 9.9
+        window(&mut limiter, 0.01, 1);
+        limits.push(limiter.limit());
+        // So does latency more than twice the best window's
+        window(&mut limiter, 0.025, 0);
+        limits.push(limiter.limit());
+        // But not latency within it
+        window(&mut limiter, 0.015, 0);
+        limits.push(limiter.limit());
+        assert_eq!(limits, vec![9, 10, 11, 9, 8, 9]);
+    }
+
+    #[test]
+    fn gradient_grows_while_latency_holds_and_shrinks_when_it_rises() {
+        let mut limiter = ConcurrencyLimiter::new(Scheduler::Gradient, 16, 1000);
+        let mut limits = vec![limiter.limit()];
+        for _ in 0..5 {
+            window(&mut limiter, 0.01, 0);
+            limits.push(limiter.limit());
+        }
+        assert!(
+            limits.windows(2).all(|pair| pair[0] <= pair[1]),
+            "{:?}",
+            limits
+        );
+        assert!(limits[5] > limits[0], "{:?}", limits);
+
+        let before = limiter.limit();
+        window(&mut limiter, 0.1, 0);
+        assert!(limiter.limit() < before);
+
+        let before = limiter.limit();
+        window(&mut limiter, 0.01, 1);
+        assert!(limiter.limit() < before);
+    }
+
+    #[test]
+    fn fixed_never_moves() {
+        let mut limiter = ConcurrencyLimiter::new(Scheduler::Fixed, 8, 100);
+        window(&mut limiter, 0.01, 0);
+        window(&mut limiter, 1.0, 16);
+        assert_eq!(limiter.limit(), 8);
+    }
+
+    #[test]
+    fn stays_between_one_and_the_max() {
+        let mut limiter = ConcurrencyLimiter::new(Scheduler::Aimd, 50, 10);
+        assert_eq!(limiter.limit(), 10);
+        for _ in 0..5 {
+            window(&mut limiter, 0.01, 0);
+        }
+        assert_eq!(limiter.limit(), 10);
+
+        let mut limiter = ConcurrencyLimiter::new(Scheduler::Aimd, 0, 10);
+        assert_eq!(limiter.limit(), 1);
+        for _ in 0..5 {
+            window(&mut limiter, 0.01, MIN_WINDOW);
+        }
+        assert_eq!(limiter.limit(), 1);
+
+        let mut limiter = ConcurrencyLimiter::new(Scheduler::Gradient, 10, 10);
+        for _ in 0..5 {
+            window(&mut limiter, 0.01, 0);
+        }
+        assert_eq!(limiter.limit(), 10);
+    }
+
+    #[tokio::test]
+    async fn run_limited_by_keeps_to_the_limit() {
+        let mut limiter = ConcurrencyLimiter::new(Scheduler::Fixed, 4, 4);
+        let in_flight = AtomicUsize::new(0);
+        let max_in_flight = AtomicUsize::new(0);
+        let tasks = (0..20).map(|idx| {
+            let (in_flight, max_in_flight) = (&in_flight, &max_in_flight);
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                idx % 5 != 0
+            }
+        });
+        let outputs = run_limited_by(tasks, &mut limiter, |succeeded| *succeeded)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(outputs.len(), 20);
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 4);
+        // The first window of 16 had 4 failures, the last 4 requests are still in the window
+        assert_eq!(limiter.history.len(), 1);
+        assert_eq!(limiter.window.len(), 4);
+    }
+}
@@ -0,0 +1,339 @@
+use std::time::Instant;
+
+use clap::Parser;
+use futures::{stream::BoxStream, StreamExt};
+use object_store::{path::Path, ObjectMeta, ObjectStore, PutPayload};
+use object_store_bench::{
+    errors::{ErrorArgs, ErrorCounts},
+    namespace::RunNamespace,
+    phase::run_phase,
+    results::{IterationResult, RunResults},
+    stats::LatencySummary,
+    store::StoreArgs,
+    timeline::TimelineArgs,
+    timing::TimingArgs,
+};
+use rand::seq::SliceRandom;
+
+/// Populates a prefix tree and measures full, delimited and offset listing.
+///
+/// Requests are counted per listing call rather than per page: object_store fetches the pages
+/// of a listing itself and doesn't expose where their boundaries are.
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    store: StoreArgs,
+
+    #[command(flatten)]
+    timeline: TimelineArgs,
+
+    /// Number of child prefixes under each prefix
+    #[arg(long)]
+    fan_out: Option<u64>,
+
+    /// Number of levels of prefixes above the objects
+    #[arg(long)]
+    depth: Option<u32>,
+
+    /// Number of objects in each leaf prefix
+    #[arg(long)]
+    objects_per_prefix: Option<u64>,
+
+    /// Maximum concurrent requests while populating and for concurrent listing
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_concurrency: Option<usize>,
+
+    /// Number of offset listings per iteration
+    #[arg(long)]
+    num_offsets: Option<usize>,
+
+    /// Stop each offset listing after this many objects
+    #[arg(long)]
+    offset_limit: Option<usize>,
+
+    #[arg(long)]
+    skip_upload: bool,
+
+    #[arg(long)]
+    num_iterations: Option<u32>,
+
+    #[command(flatten)]
+    timing: TimingArgs,
+
+    #[command(flatten)]
+    errors: ErrorArgs,
+
+    /// Reuse the namespace of an earlier run instead of creating a new one
+    #[arg(long)]
+    run_id: Option<String>,
+
+    /// Delete everything written under the run's namespace once the run finishes
+    #[arg(long)]
+    cleanup: bool,
+
+    /// Write machine readable results to this file
+    #[arg(long)]
+    results: Option<String>,
+}
+
+#[derive(Default)]
+struct ListStats {
+    num_objects: u64,
+    /// Latency of each listing call, until it returned every object wanted.  The store may
+    /// fetch several pages for one call, but object_store doesn't expose how many.
+    list_call_latencies: Vec<f64>,
+    /// Calls that failed, their latency is included above
+    errors: ErrorCounts,
+}
+
+impl ListStats {
+    fn merge(&mut self, other: ListStats) {
+        self.num_objects += other.num_objects;
+        self.list_call_latencies.extend(other.list_call_latencies);
+        self.errors.merge(&other.errors);
+    }
+}
+
+/// Drains a listing, or stops once it has returned `limit` objects or failed
+async fn drain_listing(
+    mut listing: BoxStream<'_, object_store::Result<ObjectMeta>>,
+    limit: Option<usize>,
+) -> ListStats {
+    let mut stats = ListStats::default();
+    let start = Instant::now();
+    while limit.is_none_or(|limit| (stats.num_objects as usize) < limit) {
+        match listing.next().await {
+            Some(Ok(_)) => stats.num_objects += 1,
+            Some(Err(e)) => {
+                log::debug!("Listing failed: {}", e);
+                stats.errors.record(&e);
+                break;
+            }
+            None => break,
+        }
+    }
+    stats
+        .list_call_latencies
+        .push(start.elapsed().as_secs_f64());
+    stats
+}
+
+/// Walks the tree with one `list_with_delimiter` call per prefix
+async fn walk_delimited(store: &dyn ObjectStore, root: &Path) -> ListStats {
+    let mut stats = ListStats::default();
+    let mut prefixes = vec![root.clone()];
+    while let Some(prefix) = prefixes.pop() {
+        let start = Instant::now();
+        let listing = store.list_with_delimiter(Some(&prefix)).await;
+        stats
+            .list_call_latencies
+            .push(start.elapsed().as_secs_f64());
+        match listing {
+            Ok(listing) => {
+                stats.num_objects += listing.objects.len() as u64;
+                prefixes.extend(listing.common_prefixes);
+            }
+            // Skip the part of the tree under this prefix
+            Err(e) => {
+                log::debug!("Listing {} failed: {}", prefix, e);
+                stats.errors.record(&e);
+            }
+        }
+    }
+    stats
+}
+
+fn leaf_prefixes(root: &Path, fan_out: u64, depth: u32) -> Vec<Path> {
+    let mut prefixes = vec![root.clone()];
+    for level in 0..depth {
+        prefixes = prefixes
+            .iter()
+            .flat_map(|prefix| (0..fan_out).map(move |i| prefix.child(format!("d{}-{}", level, i))))
+            .collect();
+    }
+    prefixes
+}
+
+/// Reports one kind of listing, adding its errors to the iteration's
+fn report(
+    label: &str,
+    elapsed: f64,
+    mut stats: ListStats,
+    iteration_errors: &mut ErrorCounts,
+) -> IterationResult {
+    iteration_errors.merge(&stats.errors);
+    let num_list_calls = stats.list_call_latencies.len() as u64;
+    let latency = LatencySummary::from_samples(&mut stats.list_call_latencies);
+    println!(
+        "Total {} listing took {:?} seconds ({} objects, {} list calls, {} objects/s {}{})",
+        label,
+        elapsed,
+        stats.num_objects,
+        num_list_calls,
+        stats.num_objects as f64 / elapsed,
+        latency
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default(),
+        stats.errors.suffix()
+    );
+    IterationResult {
+        label: Some(label.to_string()),
+        elapsed_secs: elapsed,
+        num_requests: num_list_calls,
+        num_objects: Some(stats.num_objects),
+        latency,
+        errors: stats.errors.to_map(),
+        ..Default::default()
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    object_store_bench::retries::init_logging();
+
+    let args = Args::parse();
+
+    let fan_out = args.fan_out.unwrap_or(10);
+    let depth = args.depth.unwrap_or(3);
+    let objects_per_prefix = args.objects_per_prefix.unwrap_or(10);
+    let max_concurrency = args.max_concurrency.unwrap_or(64);
+    let num_offsets = args.num_offsets.unwrap_or(10);
+    let offset_limit = args.offset_limit.unwrap_or(100);
+    let num_iterations = args.num_iterations.unwrap_or(3);
+
+    if args.skip_upload && args.run_id.is_none() {
+        eprintln!("--skip-upload requires the --run-id of the run that uploaded the data");
+        std::process::exit(1);
+    }
+
+    let (store, base_path) = args.store.build();
+    let timeline = args.timeline.start();
+    let store = timeline.wrap(store);
+    let namespace = RunNamespace::new(&base_path, args.run_id);
+    println!("Run id: {}", namespace.run_id);
+
+    let root = namespace.path("tree");
+    let leaves = leaf_prefixes(&root, fan_out, depth);
+    let object_paths = leaves
+        .iter()
+        .flat_map(|leaf| (0..objects_per_prefix).map(move |i| leaf.child(format!("obj-{}", i))))
+        .collect::<Vec<_>>();
+
+    let mut results = RunResults::new("list", &namespace.run_id, namespace.prefix.as_ref());
+    args.store.record(&mut results);
+    // Page boundaries aren't visible through object_store, so say what was counted instead
+    results
+        .summary
+        .insert("requests_counted".to_string(), "list_calls".into());
+    let mut stopped_early = false;
+    if !args.skip_upload {
+        println!(
+            "Populating {} objects across {} prefixes",
+            object_paths.len(),
+            leaves.len()
+        );
+        let populate = run_phase(object_paths.len() as u64, max_concurrency, |idx| {
+            let store = store.clone();
+            let path = &object_paths[idx as usize];
+            async move {
+                store
+                    .put(path, PutPayload::from_static(b"x"))
+                    .await
+                    .map(|_| 1)
+            }
+        })
+        .await;
+        println!(
+            "Populating took {:?} seconds{}",
+            populate.elapsed_secs,
+            populate.errors.suffix()
+        );
+        stopped_early =
+            args.errors
+                .should_stop(&populate.errors, populate.num_requests(), &mut results);
+    }
+
+    let mut schedule = args.timing.schedule(num_iterations);
+    while !stopped_early && schedule.next_iteration() {
+        let mut iteration = Vec::new();
+        let mut errors = ErrorCounts::default();
+        let start = Instant::now();
+        let stats = drain_listing(store.list(Some(&root)), None).await;
+        iteration.push(report(
+            "full",
+            start.elapsed().as_secs_f64(),
+            stats,
+            &mut errors,
+        ));
+
+        let start = Instant::now();
+        let stats = walk_delimited(store.as_ref(), &root).await;
+        iteration.push(report(
+            "delimited",
+            start.elapsed().as_secs_f64(),
+            stats,
+            &mut errors,
+        ));
+
+        let offsets = object_paths
+            .choose_multiple(&mut rand::thread_rng(), num_offsets)
+            .collect::<Vec<_>>();
+        let start = Instant::now();
+        let mut stats = ListStats::default();
+        for offset in offsets {
+            stats.merge(
+                drain_listing(
+                    store.list_with_offset(Some(&root), offset),
+                    Some(offset_limit),
+                )
+                .await,
+            );
+        }
+        iteration.push(report(
+            "offset",
+            start.elapsed().as_secs_f64(),
+            stats,
+            &mut errors,
+        ));
+
+        // Sibling prefixes directly under the root, listed at the same time
+        let siblings = leaf_prefixes(&root, fan_out, depth.min(1));
+        let start = Instant::now();
+        let all_stats = futures::stream::iter(siblings.iter().map(|prefix| {
+            let store = store.clone();
+            async move { drain_listing(store.list(Some(prefix)), None).await }
+        }))
+        .buffer_unordered(max_concurrency)
+        .collect::<Vec<_>>()
+        .await;
+        let mut stats = ListStats::default();
+        all_stats.into_iter().for_each(|s| stats.merge(s));
+        iteration.push(report(
+            "concurrent",
+            start.elapsed().as_secs_f64(),
+            stats,
+            &mut errors,
+        ));
+
+        let num_requests = iteration.iter().map(|result| result.num_requests).sum();
+        stopped_early = args.errors.should_stop(&errors, num_requests, &mut results);
+        schedule.record(iteration, &mut results);
+    }
+    schedule.finish(&mut results);
+
+    timeline.finish(&mut results);
+    if let Some(results_path) = args.results {
+        results.write(results_path);
+    }
+
+    if args.cleanup {
+        let num_deleted = namespace.cleanup(store.as_ref()).await;
+        println!("Deleted {} objects under {}", num_deleted, namespace.prefix);
+    }
+
+    if stopped_early {
+        std::process::exit(1);
+    }
+}
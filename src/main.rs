@@ -1,20 +1,27 @@
 use std::sync::Arc;
 
 use clap::Parser;
-use futures::StreamExt;
-use object_store::{aws::AmazonS3Builder, path::Path, ObjectStore, PutPayload};
+use object_store::{aws::AmazonS3Builder, path::Path, RetryConfig};
+use object_store_bench::{
+    client::ClientArgs,
+    deadline::DeadlineArgs,
+    download::{run_iteration, DownloadArgs},
+    errors::ErrorArgs,
+    hedge::HedgeArgs,
+    limiter::LimiterArgs,
+    namespace::RunNamespace,
+    results::{IterationResult, RunResults},
+    stats::LatencyHistogram,
+    timeline::TimelineArgs,
+    timing::TimingArgs,
+    tuning::{ConcurrencyRamp, RampStep},
+};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
-    #[arg(short, long)]
-    upload_size: Option<u64>,
-
-    #[arg(short, long)]
-    download_size: Option<u64>,
-
-    #[arg(short, long)]
-    total_size: Option<u64>,
+    #[command(flatten)]
+    download: DownloadArgs,
 
     #[arg(long)]
     access_key: Option<String>,
@@ -22,6 +29,11 @@ struct Args {
     #[arg(long)]
     secret_key: Option<String>,
 
+    #[command(flatten)]
+    client: ClientArgs,
+
+    /// Download an existing object instead of uploading one: the object at --path, or the one
+    /// uploaded by the run given with --run-id
     #[arg(short, long)]
     skip_upload: bool,
 
@@ -31,128 +43,266 @@ struct Args {
     #[arg(short, long)]
     path: Option<String>,
 
-    #[arg(short, long)]
-    num_clients: Option<u32>,
+    #[arg(long)]
+    num_iterations: Option<u32>,
 
-    #[arg(short, long)]
-    max_threads_per_client: Option<u32>,
+    #[command(flatten)]
+    timing: TimingArgs,
+
+    #[command(flatten)]
+    timeline: TimelineArgs,
+
+    #[command(flatten)]
+    errors: ErrorArgs,
 
+    #[command(flatten)]
+    deadlines: DeadlineArgs,
+
+    #[command(flatten)]
+    hedge: HedgeArgs,
+
+    #[command(flatten)]
+    limiter: LimiterArgs,
+
+    /// Reuse the namespace of an earlier run instead of creating a new one
     #[arg(long)]
-    num_iterations: Option<u32>,
+    run_id: Option<String>,
+
+    /// Delete everything written under the run's namespace once the run finishes
+    #[arg(long)]
+    cleanup: bool,
+
+    /// Write machine readable results to this file
+    #[arg(long)]
+    results: Option<String>,
+
+    /// Instead of running fixed iterations, ramp the threads per client up to
+    /// --max-threads-per-client and report the knee where throughput stops increasing
+    #[arg(long)]
+    auto_tune: bool,
 }
 
 #[tokio::main]
 async fn main() {
+    object_store_bench::retries::init_logging();
+
     let args = Args::parse();
 
+    // Without a --run-id, --skip-upload reads an object that already exists at --path rather
+    // than one an earlier run uploaded into its namespace
+    let existing_object = args.skip_upload && args.run_id.is_none();
+    let namespace = RunNamespace::new(&Path::default(), args.run_id);
+    println!("Run id: {}", namespace.run_id);
+
     let path = args.path.unwrap_or("some_file.data".to_string());
-    let path = Path::from(path.as_str());
+    let path = if existing_object {
+        Path::from(path.as_str())
+    } else {
+        namespace.path(&path)
+    };
 
-    let num_clients = args.num_clients.unwrap_or(8);
-    let threads_per_client = args.max_threads_per_client.unwrap_or(8);
+    let mut download = args.download.download(path);
+    let num_clients = download.num_clients;
+    let threads_per_client = download.threads_per_client;
 
     let num_iterations = args.num_iterations.unwrap_or(5);
+    let mut limiter = args
+        .limiter
+        .limiter((num_clients * threads_per_client) as usize);
+    if limiter.is_some() && args.auto_tune {
+        eprintln!("--scheduler only applies to runs without --auto-tune");
+        std::process::exit(1);
+    }
 
-    let total_size = args.total_size.unwrap_or(1024 * 1024 * 1024);
-    let upload_size = args.upload_size.unwrap_or(8 * 1024 * 1024);
-    let download_size = args.download_size.unwrap_or(32 * 1024 * 1024);
-
-    let make_store = move || {
-        let mut store = AmazonS3Builder::new()
-            .with_bucket_name(args.bucket.clone())
-            .with_region("us-east-1");
-        if let Some(access_key) = args.access_key.clone() {
-            store = store.with_access_key_id(access_key);
-        }
-        if let Some(secret_key) = args.secret_key.clone() {
-            store = store.with_secret_access_key(secret_key);
+    let timeline = args.timeline.start();
+    let retry = args.client.retry_config(RetryConfig::default());
+    let client_options = args.client.client_options();
+    let make_store = {
+        let timeline = &timeline;
+        let retry = &retry;
+        let client_options = &client_options;
+        move || {
+            let mut store = AmazonS3Builder::new()
+                .with_bucket_name(args.bucket.clone())
+                .with_region("us-east-1")
+                .with_retry(retry.clone())
+                .with_client_options(client_options.clone());
+            if let Some(access_key) = args.access_key.clone() {
+                store = store.with_access_key_id(access_key);
+            }
+            if let Some(secret_key) = args.secret_key.clone() {
+                store = store.with_secret_access_key(secret_key);
+            }
+            timeline.wrap(Arc::new(store.build().unwrap()))
         }
-        Arc::new(store.build().unwrap())
     };
 
     let store = make_store();
 
-    let total_size = if !args.skip_upload {
-        // Upload file
-        let mut bytes_written = 0;
-        println!(
-            "Uploading {} bytes of data in chunks of {}",
-            total_size, upload_size
-        );
-        // Just initialize whatever garbage
-        let mut data = bytes::BytesMut::with_capacity(upload_size as usize);
-        unsafe { data.set_len(upload_size as usize) };
-        let data = data.freeze();
-        let mut multipart = store.put_multipart(&path).await.unwrap();
-        let total_start = std::time::Instant::now();
-        while bytes_written < total_size {
-            let start = std::time::Instant::now();
-            println!("About to upload {} bytes of data", data.len());
-            multipart
-                .put_part(PutPayload::from_bytes(data.clone()))
-                .await
-                .unwrap();
-            println!("Upload took {:?} seconds", start.elapsed().as_secs_f64());
-            bytes_written += upload_size;
+    // How much the dashboard should expect to move, only known for a fixed number of iterations
+    let expected_downloads = |total_size: u64| {
+        if args.auto_tune || args.timing.duration.is_some() {
+            0
+        } else {
+            total_size * num_iterations as u64
         }
-        multipart.complete().await.unwrap();
-        println!(
-            "Total upload took {:?} seconds",
-            total_start.elapsed().as_secs_f64()
-        );
+    };
+    if !args.skip_upload {
+        timeline.expect_bytes(download.total_size + expected_downloads(download.total_size));
+    }
 
-        bytes_written
+    download.total_size = if !args.skip_upload {
+        match download.upload(store.as_ref()).await {
+            Ok(bytes_written) => bytes_written,
+            Err(e) => {
+                eprintln!("Upload failed and was aborted: {}", e);
+                std::process::exit(1);
+            }
+        }
     } else {
-        let meta = store.head(&path).await.unwrap();
-        meta.size as u64
+        let meta = match store.head(&download.path).await {
+            Ok(meta) => meta,
+            Err(e) => {
+                eprintln!("Couldn't read {}: {}", download.path, e);
+                std::process::exit(1);
+            }
+        };
+        let total_size = meta.size as u64;
+        timeline.expect_bytes(expected_downloads(total_size));
+        total_size
     };
 
-    for _ in 0..num_iterations {
-        let mut task_idx = 0;
-        let mut read_tasks = Vec::with_capacity(num_clients as usize);
-        for _ in 0..num_clients {
-            let store = make_store();
-            read_tasks.push((store, Vec::new()));
+    let mut results = RunResults::new("download", &namespace.run_id, namespace.prefix.as_ref());
+    args.client.record(&retry, &mut results);
+    let hedger = args.hedge.hedger();
+    let mut stopped_early = false;
+    if args.auto_tune {
+        // Each client gets its own connection pool, so ramp the requests in flight per client
+        // and keep the total a multiple of the number of clients
+        let mut ramp = ConcurrencyRamp::new(
+            num_clients as usize,
+            (num_clients * threads_per_client) as usize,
+        );
+        while let Some(concurrency) = ramp.next_concurrency() {
+            let threads_per_client = concurrency / num_clients as usize;
+            let (outcome, latency) = run_iteration(
+                &make_store,
+                &download,
+                threads_per_client,
+                args.errors.include_failed_latencies,
+                &args.deadlines,
+                hedger.as_ref(),
+                None,
+            )
+            .await;
+            let mibps = outcome.num_bytes as f64 / outcome.elapsed_secs / (1024.0 * 1024.0);
+            let p99 = latency.as_ref().map_or(f64::INFINITY, |l| l.p99);
+            println!(
+                "{} threads per client ({} in flight): {} MiB/s p99={:?} seconds{}{}",
+                threads_per_client,
+                concurrency,
+                mibps,
+                p99,
+                outcome.deadline_suffix(),
+                outcome.errors.suffix()
+            );
+            ramp.record(RampStep {
+                concurrency,
+                throughput: mibps,
+                p99,
+            });
+            stopped_early =
+                args.errors
+                    .should_stop(&outcome.errors, outcome.num_requests(), &mut results);
+            results.iterations.push(IterationResult {
+                concurrency: Some(concurrency),
+                elapsed_secs: outcome.elapsed_secs,
+                num_requests: outcome.num_requests(),
+                num_bytes: outcome.num_bytes,
+                latency,
+                errors: outcome.errors.to_map(),
+                timed_out: args.deadlines.op_timeout.map(|_| outcome.timed_out),
+                cancelled: args.deadlines.iteration_deadline.map(|_| outcome.cancelled),
+                ..Default::default()
+            });
+            if stopped_early {
+                break;
+            }
         }
-        while (task_idx * download_size) < total_size {
-            let client_idx = (task_idx % num_clients as u64) as usize;
-            let path = path.clone();
-            let read_start = task_idx * download_size;
-            let read_end = read_start + download_size;
-            let store = read_tasks[client_idx].0.clone();
-            read_tasks[client_idx].1.push(async move {
-                let start = std::time::Instant::now();
-                store
-                    .get_range(&path, read_start as usize..read_end as usize)
-                    .await
-                    .unwrap();
+        ramp.report("MiB/s", &mut results);
+    } else {
+        let mut schedule = args.timing.schedule(num_iterations);
+        while schedule.next_iteration() {
+            let (outcome, latency) = run_iteration(
+                &make_store,
+                &download,
+                threads_per_client as usize,
+                args.errors.include_failed_latencies,
+                &args.deadlines,
+                hedger.as_ref(),
+                limiter.as_mut(),
+            )
+            .await;
+            println!(
+                "Total download took {:?} seconds{}{}",
+                outcome.elapsed_secs,
+                outcome.deadline_suffix(),
+                outcome.errors.suffix()
+            );
+            let histogram = (args.deadlines.op_timeout.is_some()
+                || args.deadlines.iteration_deadline.is_some())
+            .then(|| {
+                let histogram = LatencyHistogram::from_samples(&outcome.latencies);
                 println!(
-                    "Download on client {} took {:?} seconds",
-                    client_idx,
-                    start.elapsed().as_secs_f64()
+                    "Completed downloads: {}\n{}",
+                    latency
+                        .as_ref()
+                        .map_or("none".to_string(), ToString::to_string),
+                    histogram
                 );
+                histogram
             });
-            task_idx += 1;
+            stopped_early =
+                args.errors
+                    .should_stop(&outcome.errors, outcome.num_requests(), &mut results);
+            let iteration = IterationResult {
+                // Where the adaptive limit ended up
+                concurrency: limiter.as_ref().map(|limiter| limiter.limit()),
+                elapsed_secs: outcome.elapsed_secs,
+                num_requests: outcome.num_requests(),
+                num_bytes: outcome.num_bytes,
+                latency,
+                histogram,
+                errors: outcome.errors.to_map(),
+                timed_out: args.deadlines.op_timeout.map(|_| outcome.timed_out),
+                cancelled: args.deadlines.iteration_deadline.map(|_| outcome.cancelled),
+                ..Default::default()
+            };
+            schedule.record(vec![iteration], &mut results);
+            if stopped_early {
+                break;
+            }
         }
+        schedule.finish(&mut results);
+    }
 
-        let total_start = std::time::Instant::now();
-        let read_tasks = read_tasks
-            .into_iter()
-            .map(|tasks| {
-                futures::stream::iter(tasks.1)
-                    .buffer_unordered(threads_per_client as usize)
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
-        futures::stream::iter(read_tasks)
-            .buffer_unordered(num_clients as usize)
-            .collect::<Vec<_>>()
-            .await;
+    if let Some(hedger) = &hedger {
+        hedger.report(&mut results).await;
+    }
+    if let Some(limiter) = &limiter {
+        limiter.report(&mut results);
+    }
+    timeline.finish(&mut results);
+    if let Some(results_path) = args.results {
+        results.write(results_path);
+    }
 
-        let total_elapsed = total_start.elapsed();
-        println!(
-            "Total download took {:?} seconds",
-            total_elapsed.as_secs_f64()
-        );
+    if args.cleanup {
+        let num_deleted = namespace.cleanup(store.as_ref()).await;
+        println!("Deleted {} objects under {}", num_deleted, namespace.prefix);
+    }
+
+    if stopped_early {
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,552 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use object_store::{path::Path, ObjectStore, PutPayload};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::{
+    arrivals::{run_open_loop, Arrivals},
+    data::random_bytes,
+    distribution::SizeDistribution,
+    errors::ErrorCounts,
+    phase::PhaseOutcome,
+    results::IterationResult,
+};
+
+/// An operation the mixed workload can issue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OpKind {
+    /// Read a whole object
+    Get,
+    /// Read part of an object
+    GetRange,
+    /// Write a new object
+    Put,
+    Head,
+    /// List one of the shards the data objects are spread across
+    List,
+    /// Delete an object written by an earlier `put`
+    Delete,
+}
+
+impl OpKind {
+    const ALL: [OpKind; 6] = [
+        OpKind::Get,
+        OpKind::GetRange,
+        OpKind::Put,
+        OpKind::Head,
+        OpKind::List,
+        OpKind::Delete,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OpKind::Get => "get",
+            OpKind::GetRange => "get_range",
+            OpKind::Put => "put",
+            OpKind::Head => "head",
+            OpKind::List => "list",
+            OpKind::Delete => "delete",
+        }
+    }
+
+    fn default_size(&self) -> Option<SizeDistribution> {
+        match self {
+            OpKind::GetRange => Some(SizeDistribution::Fixed(64 * 1024)),
+            OpKind::Put => Some(SizeDistribution::Fixed(1024 * 1024)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for OpKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for OpKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OpKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| {
+                let names = OpKind::ALL.map(|kind| kind.name());
+                format!(
+                    "unknown operation '{}', expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// One operation in a mix and how often it is picked, parsed from `<op>:<weight>[:<sizes>]`,
+/// e.g. `get_range:70:fixed:65536` or `list:10`, or from a `{ op, weight, size }` table in a
+/// scenario file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "OpSpecDef")]
+pub struct OpSpec {
+    pub kind: OpKind,
+    pub weight: f64,
+    /// Bytes per request for `get_range` and `put`
+    pub size: Option<SizeDistribution>,
+}
+
+impl FromStr for OpSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        let kind = parts.next().unwrap_or_default().parse::<OpKind>()?;
+        let weight = parts
+            .next()
+            .ok_or_else(|| format!("expected <op>:<weight>[:<sizes>], got '{}'", s))?
+            .parse::<f64>()
+            .map_err(|e| format!("invalid weight in '{}': {}", s, e))?;
+        if weight <= 0.0 || !weight.is_finite() {
+            return Err(format!("weight in '{}' must be positive", s));
+        }
+        let size = match parts.next() {
+            Some(size) => Some(size.parse::<SizeDistribution>()?),
+            None => kind.default_size(),
+        };
+        Ok(Self { kind, weight, size })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OpSpecDef {
+    op: String,
+    weight: f64,
+    size: Option<String>,
+}
+
+impl TryFrom<OpSpecDef> for OpSpec {
+    type Error = String;
+
+    fn try_from(def: OpSpecDef) -> Result<Self, Self::Error> {
+        let spec = match def.size {
+            Some(size) => format!("{}:{}:{}", def.op, def.weight, size),
+            None => format!("{}:{}", def.op, def.weight),
+        };
+        spec.parse()
+    }
+}
+
+/// A weighted mix of operations sharing one concurrency budget
+#[derive(Debug, Clone)]
+pub struct MixSpec {
+    ops: Vec<OpSpec>,
+    total_weight: f64,
+}
+
+impl MixSpec {
+    pub fn new(ops: Vec<OpSpec>) -> Result<Self, String> {
+        if ops.is_empty() {
+            return Err("a mix needs at least one operation".to_string());
+        }
+        let total_weight = ops.iter().map(|op| op.weight).sum();
+        Ok(Self { ops, total_weight })
+    }
+
+    pub fn ops(&self) -> &[OpSpec] {
+        &self.ops
+    }
+
+    /// Largest object `put` can write with this mix
+    pub fn max_put_size(&self) -> u64 {
+        self.ops
+            .iter()
+            .filter(|op| op.kind == OpKind::Put)
+            .filter_map(|op| op.size.as_ref().map(SizeDistribution::max))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn choose(&self, rng: &mut impl Rng) -> &OpSpec {
+        let mut pick = rng.gen_range(0.0..self.total_weight);
+        for op in &self.ops {
+            if pick < op.weight {
+                return op;
+            }
+            pick -= op.weight;
+        }
+        self.ops.last().unwrap()
+    }
+}
+
+impl fmt::Display for MixSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ops = self
+            .ops
+            .iter()
+            .map(|op| {
+                format!(
+                    "{:.0}% {}{}",
+                    op.weight / self.total_weight * 100.0,
+                    op.kind,
+                    op.size
+                        .as_ref()
+                        .map(|size| format!(" ({})", size))
+                        .unwrap_or_default()
+                )
+            })
+            .collect::<Vec<_>>();
+        f.write_str(&ops.join(", "))
+    }
+}
+
+/// Data objects are spread over this many prefixes so `list` has something of a sensible size
+const LIST_SHARDS: u64 = 16;
+
+/// Runs a mix of operations against a set of pre-populated data objects
+pub struct MixedWorkload {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+    num_objects: u64,
+    object_size: u64,
+    /// Random data that every write takes a slice of
+    data: Bytes,
+    next_write: AtomicU64,
+    /// Objects written by `put` that `delete` can remove
+    written: Mutex<Vec<Path>>,
+}
+
+impl MixedWorkload {
+    pub fn new(
+        store: Arc<dyn ObjectStore>,
+        prefix: Path,
+        num_objects: u64,
+        object_size: u64,
+        max_put_size: u64,
+    ) -> Self {
+        Self {
+            store,
+            prefix,
+            num_objects,
+            object_size,
+            data: random_bytes(max_put_size.max(object_size)),
+            next_write: AtomicU64::new(0),
+            written: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn data_path(&self, idx: u64) -> Path {
+        self.prefix
+            .child("data")
+            .child((idx % LIST_SHARDS).to_string())
+            .child(idx.to_string())
+    }
+
+    /// Writes the data objects that reads operate on
+    pub async fn populate(&self, concurrency: usize) -> PhaseOutcome {
+        crate::phase::run_phase(self.num_objects, concurrency, |idx| {
+            let path = self.data_path(idx);
+            let payload = PutPayload::from_bytes(self.data.slice(0..self.object_size as usize));
+            async move {
+                self.store
+                    .put(&path, payload)
+                    .await
+                    .map(|_| self.object_size)
+            }
+        })
+        .await
+    }
+
+    /// Issues one operation, returning the number of bytes transferred
+    pub async fn run_op(&self, op: &OpSpec) -> object_store::Result<u64> {
+        let (target, size) = {
+            let mut rng = rand::thread_rng();
+            (
+                self.data_path(rng.gen_range(0..self.num_objects)),
+                op.size.as_ref().map(|size| size.sample(&mut rng)),
+            )
+        };
+        match op.kind {
+            OpKind::Get => Ok(self.store.get(&target).await?.bytes().await?.len() as u64),
+            OpKind::GetRange => {
+                let len = size.unwrap_or(0).min(self.object_size) as usize;
+                let start = rand::thread_rng().gen_range(0..=self.object_size as usize - len);
+                Ok(self
+                    .store
+                    .get_range(&target, start..start + len)
+                    .await?
+                    .len() as u64)
+            }
+            OpKind::Put => {
+                let size = size.unwrap_or(0) as usize;
+                let idx = self.next_write.fetch_add(1, Ordering::Relaxed);
+                let path = self.prefix.child("writes").child(idx.to_string());
+                self.store
+                    .put(&path, PutPayload::from_bytes(self.data.slice(0..size)))
+                    .await?;
+                self.written.lock().unwrap().push(path);
+                Ok(size as u64)
+            }
+            OpKind::Head => {
+                self.store.head(&target).await?;
+                Ok(0)
+            }
+            OpKind::List => {
+                let shard = self
+                    .prefix
+                    .child("data")
+                    .child(rand::thread_rng().gen_range(0..LIST_SHARDS).to_string());
+                let num_objects = self
+                    .store
+                    .list(Some(&shard))
+                    .try_fold(0u64, |n, _| async move { Ok(n + 1) })
+                    .await?;
+                log::trace!("Listed {} objects under {}", num_objects, shard);
+                Ok(0)
+            }
+            OpKind::Delete => {
+                let path = {
+                    let mut written = self.written.lock().unwrap();
+                    if written.is_empty() {
+                        None
+                    } else {
+                        let idx = rand::thread_rng().gen_range(0..written.len());
+                        Some(written.swap_remove(idx))
+                    }
+                };
+                match path {
+                    Some(path) => self.store.delete(&path).await.map(|_| 0),
+                    None => {
+                        log::debug!("Nothing to delete yet, add some put to the mix");
+                        Ok(0)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs `num_ops` operations picked from `mix` with at most `concurrency` in flight
+    pub async fn run(&self, mix: &MixSpec, num_ops: u64, concurrency: usize) -> MixOutcome {
+        let mut rng = StdRng::from_entropy();
+        let tasks = (0..num_ops).map(|_| {
+            let op = mix.choose(&mut rng).clone();
+            async move {
+                let start = Instant::now();
+                let result = self.run_op(&op).await;
+                (op.kind, start.elapsed().as_secs_f64(), result)
+            }
+        });
+
+        let start = Instant::now();
+        let mut per_op = BTreeMap::<OpKind, PhaseOutcome>::new();
+        let mut completed = futures::stream::iter(tasks).buffer_unordered(concurrency);
+        while let Some((kind, latency, result)) = completed.next().await {
+            per_op.entry(kind).or_default().record(latency, &result);
+        }
+        let elapsed_secs = start.elapsed().as_secs_f64();
+        for outcome in per_op.values_mut() {
+            outcome.elapsed_secs = elapsed_secs;
+        }
+        MixOutcome {
+            elapsed_secs,
+            per_op,
+            target_rate: None,
+        }
+    }
+
+    /// Runs `num_ops` operations picked from `mix`, starting them when `arrivals` says
+    pub async fn run_open_loop(
+        &self,
+        mix: &MixSpec,
+        num_ops: u64,
+        arrivals: Arrivals,
+    ) -> MixOutcome {
+        let mut rng = StdRng::from_entropy();
+        let tasks = (0..num_ops).map(|_| {
+            let op = mix.choose(&mut rng).clone();
+            async move { (op.kind, self.run_op(&op).await) }
+        });
+
+        let mut per_op = BTreeMap::<OpKind, PhaseOutcome>::new();
+        let stats = run_open_loop(tasks, arrivals, |latency, (kind, result)| {
+            per_op.entry(kind).or_default().record(latency, &result)
+        })
+        .await;
+        stats.log();
+        for outcome in per_op.values_mut() {
+            outcome.elapsed_secs = stats.elapsed_secs;
+        }
+        MixOutcome {
+            elapsed_secs: stats.elapsed_secs,
+            per_op,
+            target_rate: Some(arrivals.rate()),
+        }
+    }
+}
+
+/// Per operation type metrics from one run of a mix
+#[derive(Debug)]
+pub struct MixOutcome {
+    pub elapsed_secs: f64,
+    pub per_op: BTreeMap<OpKind, PhaseOutcome>,
+    /// Requests per second across all operation types, if the mix ran open-loop
+    pub target_rate: Option<f64>,
+}
+
+impl MixOutcome {
+    pub fn num_requests(&self) -> u64 {
+        self.per_op.values().map(PhaseOutcome::num_requests).sum()
+    }
+
+    /// Errors across all operation types
+    pub fn errors(&self) -> ErrorCounts {
+        let mut errors = ErrorCounts::default();
+        for outcome in self.per_op.values() {
+            errors.merge(&outcome.errors);
+        }
+        errors
+    }
+
+    pub fn include_failed_latencies(&mut self) {
+        self.per_op
+            .values_mut()
+            .for_each(PhaseOutcome::include_failed_latencies);
+    }
+
+    /// Prints a line per operation type plus a total, returning a result for each
+    pub fn report(self) -> Vec<IterationResult> {
+        let num_requests = self.num_requests();
+        let num_bytes = self.per_op.values().map(|o| o.num_bytes).sum::<u64>();
+        println!(
+            "Total mix took {:?} seconds ({} ops/s{} {} MiB/s)",
+            self.elapsed_secs,
+            num_requests as f64 / self.elapsed_secs,
+            self.target_rate
+                .map(|rate| format!(" of {} target", rate))
+                .unwrap_or_default(),
+            num_bytes as f64 / self.elapsed_secs / (1024.0 * 1024.0),
+        );
+        self.per_op
+            .into_iter()
+            .map(|(kind, outcome)| outcome.report(kind.name()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_op() {
+        use SizeDistribution::*;
+        let cases = [
+            ("get:1", Ok((OpKind::Get, 1.0, None))),
+            ("head:0.5", Ok((OpKind::Head, 0.5, None))),
+            ("list:10", Ok((OpKind::List, 10.0, None))),
+            ("delete:3", Ok((OpKind::Delete, 3.0, None))),
+            (
+                "get_range:70",
+                Ok((OpKind::GetRange, 70.0, Some(Fixed(64 * 1024)))),
+            ),
+            (
+                "get_range:70:fixed:4096",
+                Ok((OpKind::GetRange, 70.0, Some(Fixed(4096)))),
+            ),
+            ("put:5", Ok((OpKind::Put, 5.0, Some(Fixed(1024 * 1024))))),
+            (
+                "put:5:uniform:1-10",
+                Ok((OpKind::Put, 5.0, Some(Uniform(1, 10)))),
+            ),
+            (
+                "put:5:choice:1,2,3",
+                Ok((OpKind::Put, 5.0, Some(Choice(vec![1, 2, 3])))),
+            ),
+            ("", Err("unknown operation ''")),
+            ("scan:1", Err("unknown operation 'scan'")),
+            ("GET:1", Err("unknown operation 'GET'")),
+            ("get", Err("expected <op>:<weight>[:<sizes>]")),
+            ("get:", Err("invalid weight")),
+            ("get:often", Err("invalid weight")),
+            ("get:0", Err("must be positive")),
+            ("get:-1", Err("must be positive")),
+            ("get:inf", Err("must be positive")),
+            ("put:1:4096", Err("expected <kind>:<params>")),
+            ("put:1:normal:4096", Err("unknown size distribution")),
+            ("put:1:uniform:10-1", Err("min 10 is larger than max 1")),
+        ];
+        for (s, expected) in cases {
+            match (s.parse::<OpSpec>(), expected) {
+                (Ok(op), Ok(expected)) => {
+                    assert_eq!((op.kind, op.weight, op.size), expected, "{}", s)
+                }
+                (Err(e), Err(expected)) => assert!(e.contains(expected), "{}: {}", s, e),
+                (result, _) => panic!("{}: unexpected {:?}", s, result),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_op_table() {
+        #[derive(Deserialize)]
+        struct Mix {
+            ops: Vec<OpSpec>,
+        }
+
+        let mix = toml::from_str::<Mix>(
+            r#"ops = [
+                { op = "get", weight = 3 },
+                { op = "put", weight = 1, size = "uniform:1-10" },
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(mix.ops[0].kind, OpKind::Get);
+        assert_eq!(mix.ops[0].size, None);
+        assert_eq!(mix.ops[1].kind, OpKind::Put);
+        assert_eq!(mix.ops[1].size, Some(SizeDistribution::Uniform(1, 10)));
+
+        for ops in [
+            r#"ops = [{ op = "scan", weight = 1 }]"#,
+            r#"ops = [{ op = "get", weight = 0 }]"#,
+            r#"ops = [{ op = "get" }]"#,
+            r#"ops = [{ op = "get", weight = 1, sizes = "fixed:1" }]"#,
+        ] {
+            assert!(toml::from_str::<Mix>(ops).is_err(), "{}", ops);
+        }
+    }
+
+    #[test]
+    fn mix() {
+        assert!(MixSpec::new(Vec::new()).is_err());
+
+        let ops = [
+            "get_range:60:fixed:4096",
+            "put:30:uniform:1-10",
+            "put:10:fixed:100",
+        ]
+        .map(|op| op.parse::<OpSpec>().unwrap());
+        let mix = MixSpec::new(ops.to_vec()).unwrap();
+        assert_eq!(mix.max_put_size(), 100);
+        assert_eq!(
+            mix.to_string(),
+            "60% get_range (fixed:4096), 30% put (uniform:1-10), 10% put (fixed:100)"
+        );
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let num_gets = (0..10000)
+            .filter(|_| mix.choose(&mut rng).kind == OpKind::GetRange)
+            .count();
+        assert!((5500..6500).contains(&num_gets), "{}", num_gets);
+    }
+}
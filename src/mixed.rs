@@ -0,0 +1,171 @@
+use clap::Parser;
+use object_store_bench::{
+    arrivals::Arrivals,
+    errors::ErrorArgs,
+    mix::{MixSpec, MixedWorkload, OpSpec},
+    namespace::RunNamespace,
+    results::{IterationResult, RunResults},
+    store::StoreArgs,
+    timeline::TimelineArgs,
+    timing::TimingArgs,
+};
+
+/// Runs a weighted mix of reads, writes, heads and lists with one shared concurrency budget
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    store: StoreArgs,
+
+    #[command(flatten)]
+    timeline: TimelineArgs,
+
+    #[command(flatten)]
+    errors: ErrorArgs,
+
+    /// An operation in the mix as `<op>:<weight>[:<sizes>]`, e.g. `get_range:70:fixed:65536`,
+    /// `put:20:uniform:1024-1048576` or `list:10`.  Repeat for each operation.
+    #[arg(long = "op", required = true)]
+    ops: Vec<OpSpec>,
+
+    /// Number of data objects for reads to target
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    num_objects: Option<u64>,
+
+    #[arg(long)]
+    object_size: Option<u64>,
+
+    /// Operations per iteration
+    #[arg(long)]
+    num_ops: Option<u64>,
+
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_concurrency: Option<usize>,
+
+    /// Run open-loop, issuing requests at `constant:<ops/s>` or `poisson:<ops/s>` however many
+    /// are in flight, instead of keeping --max-concurrency in flight
+    #[arg(long)]
+    rate: Option<Arrivals>,
+
+    #[arg(long)]
+    num_iterations: Option<u32>,
+
+    #[command(flatten)]
+    timing: TimingArgs,
+
+    #[arg(long)]
+    skip_upload: bool,
+
+    /// Reuse the namespace of an earlier run instead of creating a new one
+    #[arg(long)]
+    run_id: Option<String>,
+
+    /// Delete everything written under the run's namespace once the run finishes
+    #[arg(long)]
+    cleanup: bool,
+
+    /// Write machine readable results to this file
+    #[arg(long)]
+    results: Option<String>,
+}
+
+#[tokio::main]
+async fn main() {
+    object_store_bench::retries::init_logging();
+
+    let args = Args::parse();
+
+    let num_objects = args.num_objects.unwrap_or(1000);
+    let object_size = args.object_size.unwrap_or(8 * 1024 * 1024);
+    let num_ops = args.num_ops.unwrap_or(10000);
+    let max_concurrency = args.max_concurrency.unwrap_or(64);
+    let num_iterations = args.num_iterations.unwrap_or(3);
+    let mix = match MixSpec::new(args.ops) {
+        Ok(mix) => mix,
+        Err(e) => {
+            eprintln!("Invalid mix: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if args.skip_upload && args.run_id.is_none() {
+        eprintln!("--skip-upload requires the --run-id of the run that uploaded the data");
+        std::process::exit(1);
+    }
+
+    let (store, base_path) = args.store.build();
+    let timeline = args.timeline.start();
+    let store = timeline.wrap(store);
+    let namespace = RunNamespace::new(&base_path, args.run_id);
+    println!("Run id: {}", namespace.run_id);
+    println!("Mix: {}", mix);
+
+    let workload = MixedWorkload::new(
+        store.clone(),
+        namespace.path("mixed"),
+        num_objects,
+        object_size,
+        mix.max_put_size(),
+    );
+
+    let mut results = RunResults::new("mixed", &namespace.run_id, namespace.prefix.as_ref());
+    args.store.record(&mut results);
+    let mut stopped_early = false;
+    if !args.skip_upload {
+        println!(
+            "Writing {} data objects of {} bytes",
+            num_objects, object_size
+        );
+        let populate = workload.populate(max_concurrency).await;
+        let (errors, num_requests) = (populate.errors.clone(), populate.num_requests());
+        populate.report("populate");
+        stopped_early = args.errors.should_stop(&errors, num_requests, &mut results);
+    }
+
+    let mut schedule = args.timing.schedule(num_iterations);
+    while !stopped_early && schedule.next_iteration() {
+        let mut outcome = match args.rate {
+            Some(arrivals) => workload.run_open_loop(&mix, num_ops, arrivals).await,
+            None => workload.run(&mix, num_ops, max_concurrency).await,
+        };
+        if args.errors.include_failed_latencies {
+            outcome.include_failed_latencies();
+        }
+        let (errors, num_requests) = (outcome.errors(), outcome.num_requests());
+        let concurrency = args.rate.is_none().then_some(max_concurrency);
+        let iteration = outcome
+            .report()
+            .into_iter()
+            .map(|result| IterationResult {
+                concurrency,
+                ..result
+            })
+            .collect();
+        stopped_early = args.errors.should_stop(&errors, num_requests, &mut results);
+        schedule.record(iteration, &mut results);
+        if stopped_early {
+            break;
+        }
+    }
+    schedule.finish(&mut results);
+
+    if let Some(arrivals) = args.rate {
+        results
+            .summary
+            .insert("arrivals".to_string(), arrivals.to_string().into());
+    }
+
+    timeline.finish(&mut results);
+    if let Some(results_path) = args.results {
+        results.write(results_path);
+    }
+
+    if args.cleanup {
+        let num_deleted = namespace.cleanup(store.as_ref()).await;
+        println!("Deleted {} objects under {}", num_deleted, namespace.prefix);
+    }
+
+    if stopped_early {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, OnceLock},
+};
+
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use object_store::{path::Path, MultipartUpload, ObjectStore, PutPayload, PutResult};
+use tokio::sync::{watch, Mutex};
+
+#[derive(Debug)]
+pub enum UploadError {
+    Store(object_store::Error),
+    Io(std::io::Error),
+    Interrupted,
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadError::Store(e) => write!(f, "{}", e),
+            UploadError::Io(e) => write!(f, "{}", e),
+            UploadError::Interrupted => write!(f, "interrupted by Ctrl-C"),
+        }
+    }
+}
+
+impl std::error::Error for UploadError {}
+
+impl From<object_store::Error> for UploadError {
+    fn from(e: object_store::Error) -> Self {
+        UploadError::Store(e)
+    }
+}
+
+impl From<std::io::Error> for UploadError {
+    fn from(e: std::io::Error) -> Self {
+        UploadError::Io(e)
+    }
+}
+
+/// A multipart upload that can be shared between concurrently running part uploads.
+#[derive(Debug, Clone)]
+pub struct SharedUpload {
+    inner: Arc<Mutex<Box<dyn MultipartUpload>>>,
+}
+
+impl SharedUpload {
+    pub fn new(upload: Box<dyn MultipartUpload>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(upload)),
+        }
+    }
+
+    pub async fn put_part(&self, data: PutPayload) -> object_store::Result<()> {
+        // Only hold the lock long enough to assign the part index, the upload itself
+        // runs without it so parts can be in flight concurrently.
+        let part = self.inner.lock().await.put_part(data);
+        part.await
+    }
+
+    pub async fn complete(&self) -> object_store::Result<PutResult> {
+        self.inner.lock().await.complete().await
+    }
+
+    pub async fn abort(&self) -> object_store::Result<()> {
+        self.inner.lock().await.abort().await
+    }
+}
+
+/// Subscribes to Ctrl-C, installing the process's one handler the first time.  Ctrl-C
+/// interrupts the uploads subscribed at the time, or exits the process if there are none.
+fn interrupts() -> watch::Receiver<()> {
+    static INTERRUPTS: OnceLock<watch::Sender<()>> = OnceLock::new();
+    INTERRUPTS
+        .get_or_init(|| {
+            tokio::spawn(async {
+                while tokio::signal::ctrl_c().await.is_ok() {
+                    let interrupts = INTERRUPTS.get().unwrap();
+                    if interrupts.receiver_count() == 0 {
+                        std::process::exit(130);
+                    }
+                    interrupts.send_replace(());
+                }
+            });
+            watch::channel(()).0
+        })
+        .subscribe()
+}
+
+/// Drives `work` to completion unless Ctrl-C is received first
+pub async fn until_interrupted<T, E>(
+    work: impl Future<Output = Result<T, E>>,
+) -> Result<T, UploadError>
+where
+    UploadError: From<E>,
+{
+    let mut interrupted = interrupts();
+    tokio::select! {
+        res = work => res.map_err(UploadError::from),
+        _ = interrupted.changed() => Err(UploadError::Interrupted),
+    }
+}
+
+/// Aborts an upload that failed with `reason`, logging (rather than returning) any failure
+/// to abort since the original error is the interesting one
+pub async fn abort_upload(
+    reason: &UploadError,
+    abort: impl Future<Output = object_store::Result<()>>,
+) {
+    log::warn!("Aborting multipart upload: {}", reason);
+    if let Err(abort_err) = abort.await {
+        log::error!(
+            "Failed to abort multipart upload, parts may be left behind: {}",
+            abort_err
+        );
+    }
+}
+
+/// Drives `work` (which should upload the parts and complete `upload`) to completion.
+///
+/// If `work` fails, or Ctrl-C is received before it finishes, the multipart upload is
+/// aborted so that no billable orphaned parts are left behind in the store.
+pub async fn run_or_abort<T>(
+    upload: &SharedUpload,
+    work: impl Future<Output = object_store::Result<T>>,
+) -> Result<T, UploadError> {
+    let result = until_interrupted(work).await;
+    if let Err(e) = &result {
+        abort_upload(e, upload.abort()).await;
+    }
+    result
+}
+
+/// Uploads `data` to `path` in `part_size` parts with up to `max_parallelism` parts in flight,
+/// aborting the upload if anything fails
+pub async fn upload_in_parts(
+    store: &dyn ObjectStore,
+    path: &Path,
+    data: Bytes,
+    part_size: usize,
+    max_parallelism: usize,
+) -> Result<PutResult, UploadError> {
+    let mut upload = store.put_multipart(path).await?;
+    // Part indices are assigned by `put_part`, so create every part up front (the uploads
+    // themselves don't start until polled) and then run them out of order
+    let parts = (0..data.len().max(1))
+        .step_by(part_size)
+        .map(|start| {
+            let end = (start + part_size).min(data.len());
+            upload.put_part(PutPayload::from_bytes(data.slice(start..end)))
+        })
+        .collect::<Vec<_>>();
+    let work = async {
+        futures::stream::iter(parts)
+            .buffer_unordered(max_parallelism)
+            .try_collect::<Vec<_>>()
+            .await?;
+        upload.complete().await
+    };
+    let result = until_interrupted(work).await;
+    if let Err(e) = &result {
+        abort_upload(e, upload.abort()).await;
+    }
+    result
+}
@@ -0,0 +1,88 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::{StreamExt, TryStreamExt};
+use object_store::{path::Path, ObjectStore};
+
+/// Every run writes its objects somewhere below this directory
+pub const BENCH_ROOT: &str = "object_store_bench";
+
+const RUN_ID_PREFIX: &str = "run-";
+
+/// A per-run prefix so that concurrent runs don't clobber each other's objects.
+///
+/// Objects are written to `<base>/object_store_bench/run-<unix secs>-<random>/...`.  The
+/// creation time is part of the id so that `gc` can find old runs without any extra state.
+#[derive(Debug, Clone)]
+pub struct RunNamespace {
+    pub run_id: String,
+    pub prefix: Path,
+}
+
+impl RunNamespace {
+    /// Creates a namespace for a new run, or reuses `run_id` (e.g. to read back data
+    /// uploaded by an earlier run with `--skip-upload`)
+    pub fn new(base: &Path, run_id: Option<String>) -> Self {
+        let run_id = run_id.unwrap_or_else(|| {
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            format!("{}{}-{:08x}", RUN_ID_PREFIX, secs, rand::random::<u32>())
+        });
+        let prefix = bench_root(base).child(run_id.as_str());
+        Self { run_id, prefix }
+    }
+
+    pub fn path(&self, name: &str) -> Path {
+        self.prefix
+            .parts()
+            .chain(Path::from(name).parts())
+            .collect()
+    }
+
+    pub async fn delete_all(&self, store: &dyn ObjectStore) -> object_store::Result<usize> {
+        delete_prefix(store, &self.prefix).await
+    }
+
+    /// Deletes everything the run wrote for `--cleanup`, returning the number of objects
+    /// deleted, or prints the error and exits if that fails
+    pub async fn cleanup(&self, store: &dyn ObjectStore) -> usize {
+        match self.delete_all(store).await {
+            Ok(num_deleted) => num_deleted,
+            Err(e) => {
+                eprintln!("Cleanup of {} failed: {}", self.prefix, e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+pub fn bench_root(base: &Path) -> Path {
+    base.child(BENCH_ROOT)
+}
+
+/// Returns how long ago the run with the given id was started, if `run_id` looks like an id
+/// created by [`RunNamespace::new`]
+pub fn run_age(run_id: &str) -> Option<Duration> {
+    let secs = run_id
+        .strip_prefix(RUN_ID_PREFIX)?
+        .split('-')
+        .next()?
+        .parse::<u64>()
+        .ok()?;
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH + Duration::from_secs(secs))
+        .ok()
+}
+
+/// Deletes every object under `prefix`, returning the number of objects deleted
+pub async fn delete_prefix(store: &dyn ObjectStore, prefix: &Path) -> object_store::Result<usize> {
+    let locations = store
+        .list(Some(prefix))
+        .map_ok(|meta| meta.location)
+        .boxed();
+    store
+        .delete_stream(locations)
+        .try_fold(0, |num_deleted, _| async move { Ok(num_deleted + 1) })
+        .await
+}
@@ -4,7 +4,7 @@ use tokio::fs::File;
 
 #[tokio::main]
 async fn main() {
-    let path = format!("/home/pace/dev/data/nyctaxi/year=2013/month=4/part-0.parquet");
+    let path = "/home/pace/dev/data/nyctaxi/year=2013/month=4/part-0.parquet".to_string();
     let start = std::time::Instant::now();
 
     let row_group_indices = (0..1200)
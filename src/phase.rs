@@ -0,0 +1,181 @@
+use std::{future::Future, time::Instant};
+
+use futures::StreamExt;
+
+use crate::{
+    arrivals::{run_open_loop, Arrivals},
+    errors::ErrorCounts,
+    limiter::{run_limited, ConcurrencyLimiter},
+    results::IterationResult,
+    stats::{LatencyHistogram, LatencySummary},
+};
+
+/// What happened while running one phase of a workload
+#[derive(Debug, Default)]
+pub struct PhaseOutcome {
+    pub elapsed_secs: f64,
+    /// Latencies of the requests that succeeded
+    pub latencies: Vec<f64>,
+    /// Latencies of the requests that failed
+    pub failed_latencies: Vec<f64>,
+    pub errors: ErrorCounts,
+    pub num_bytes: u64,
+    /// Requests per second the phase tried to issue, if it ran open-loop
+    pub target_rate: Option<f64>,
+    /// Requests given up on after the per-request timeout
+    pub timed_out: u64,
+    /// Requests cancelled, or never started, when the deadline passed
+    pub cancelled: u64,
+}
+
+/// Runs `op(0..num_ops)` with at most `concurrency` in flight, timing each one.
+///
+/// `op` returns the number of bytes it transferred.
+pub async fn run_phase<F, Fut>(num_ops: u64, concurrency: usize, mut op: F) -> PhaseOutcome
+where
+    F: FnMut(u64) -> Fut,
+    Fut: Future<Output = object_store::Result<u64>>,
+{
+    let tasks = (0..num_ops)
+        .map(|idx| {
+            let fut = op(idx);
+            async move {
+                let start = Instant::now();
+                let result = fut.await;
+                (start.elapsed().as_secs_f64(), result)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    let outcomes = futures::stream::iter(tasks)
+        .buffer_unordered(concurrency)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut outcome = PhaseOutcome {
+        elapsed_secs: start.elapsed().as_secs_f64(),
+        ..Default::default()
+    };
+    for (latency, result) in outcomes {
+        outcome.record(latency, &result);
+    }
+    outcome
+}
+
+/// Like [`run_phase`] but with as many ops in flight as `limiter` currently allows
+pub async fn run_phase_limited<F, Fut>(
+    num_ops: u64,
+    limiter: &mut ConcurrencyLimiter,
+    op: F,
+) -> PhaseOutcome
+where
+    F: FnMut(u64) -> Fut,
+    Fut: Future<Output = object_store::Result<u64>>,
+{
+    let start = Instant::now();
+    let outcomes = run_limited((0..num_ops).map(op), limiter)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut outcome = PhaseOutcome {
+        elapsed_secs: start.elapsed().as_secs_f64(),
+        ..Default::default()
+    };
+    for (latency, result) in outcomes {
+        outcome.record(latency, &result);
+    }
+    outcome
+}
+
+/// Like [`run_phase`] but starts each op when `arrivals` says instead of when an earlier one
+/// finishes, with latencies measured from the intended start.
+pub async fn run_phase_open_loop<F, Fut>(num_ops: u64, arrivals: Arrivals, op: F) -> PhaseOutcome
+where
+    F: FnMut(u64) -> Fut,
+    Fut: Future<Output = object_store::Result<u64>>,
+{
+    let mut outcome = PhaseOutcome {
+        target_rate: Some(arrivals.rate()),
+        ..Default::default()
+    };
+    let stats = run_open_loop((0..num_ops).map(op), arrivals, |latency, result| {
+        outcome.record(latency, &result)
+    })
+    .await;
+    stats.log();
+    outcome.elapsed_secs = stats.elapsed_secs;
+    outcome
+}
+
+impl PhaseOutcome {
+    pub fn record(&mut self, latency: f64, result: &object_store::Result<u64>) {
+        match result {
+            Ok(num_bytes) => {
+                self.latencies.push(latency);
+                self.num_bytes += num_bytes;
+            }
+            Err(e) => {
+                log::debug!("Request failed: {}", e);
+                self.failed_latencies.push(latency);
+                self.errors.record(e);
+            }
+        }
+    }
+
+    /// Counts failed requests in the latency percentiles too
+    pub fn include_failed_latencies(&mut self) {
+        self.latencies.append(&mut self.failed_latencies);
+    }
+
+    /// ` (N timed out, M cancelled at the deadline)` to append to a summary line, or nothing if
+    /// every request completed
+    pub fn deadline_suffix(&self) -> String {
+        if self.timed_out == 0 && self.cancelled == 0 {
+            String::new()
+        } else {
+            format!(
+                " ({} timed out, {} cancelled at the deadline)",
+                self.timed_out, self.cancelled
+            )
+        }
+    }
+
+    pub fn num_requests(&self) -> u64 {
+        (self.latencies.len() + self.failed_latencies.len()) as u64
+    }
+
+    /// Prints a summary line (and histogram) for the phase and converts it to a result
+    pub fn report(mut self, label: &str) -> IterationResult {
+        let num_requests = self.num_requests();
+        let histogram = LatencyHistogram::from_samples(&self.latencies);
+        let latency = LatencySummary::from_samples(&mut self.latencies);
+        println!(
+            "Total {} took {:?} seconds ({} ops/s{} {} MiB/s {}{})",
+            label,
+            self.elapsed_secs,
+            num_requests as f64 / self.elapsed_secs,
+            self.target_rate
+                .map(|rate| format!(" of {} target", rate))
+                .unwrap_or_default(),
+            self.num_bytes as f64 / self.elapsed_secs / (1024.0 * 1024.0),
+            latency
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+            self.errors.suffix()
+        );
+        log::info!("{} latency histogram:\n{}", label, histogram);
+        IterationResult {
+            label: Some(label.to_string()),
+            target_rate: self.target_rate,
+            elapsed_secs: self.elapsed_secs,
+            num_requests,
+            num_bytes: self.num_bytes,
+            latency,
+            histogram: Some(histogram),
+            errors: self.errors.to_map(),
+            ..Default::default()
+        }
+    }
+}
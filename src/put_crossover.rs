@@ -0,0 +1,244 @@
+use std::time::Instant;
+
+use clap::Parser;
+use object_store::PutPayload;
+use object_store_bench::{
+    data::random_bytes,
+    multipart::upload_in_parts,
+    namespace::RunNamespace,
+    results::{IterationResult, RunResults},
+    stats::LatencySummary,
+    store::StoreArgs,
+    timeline::TimelineArgs,
+};
+
+/// Finds the object size at which multipart uploads become faster than a single put
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    store: StoreArgs,
+
+    #[command(flatten)]
+    timeline: TimelineArgs,
+
+    /// Smallest object size to test
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    min_size: Option<u64>,
+
+    /// Largest object size to test
+    #[arg(long)]
+    max_size: Option<u64>,
+
+    /// Each object size is this many times larger than the last
+    #[arg(long)]
+    size_factor: Option<u64>,
+
+    /// Part sizes to try for the multipart uploads
+    #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(u64).range(1..))]
+    part_sizes: Vec<u64>,
+
+    /// Maximum number of parts of one object to upload concurrently
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_parallelism: Option<usize>,
+
+    /// How many times to upload each object size with each method
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    repetitions: Option<u32>,
+
+    /// Reuse the namespace of an earlier run instead of creating a new one
+    #[arg(long)]
+    run_id: Option<String>,
+
+    /// Delete everything written under the run's namespace once the run finishes
+    #[arg(long)]
+    cleanup: bool,
+
+    /// Write machine readable results to this file
+    #[arg(long)]
+    results: Option<String>,
+}
+
+/// S3 rejects parts smaller than this (other than the last one)
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+#[tokio::main]
+async fn main() {
+    object_store_bench::retries::init_logging();
+
+    let args = Args::parse();
+
+    let min_size = args.min_size.unwrap_or(1024);
+    let max_size = args.max_size.unwrap_or(1024 * 1024 * 1024);
+    let size_factor = args.size_factor.unwrap_or(4).max(2);
+    if min_size > max_size {
+        eprintln!(
+            "--min-size {} is larger than --max-size {}",
+            min_size, max_size
+        );
+        std::process::exit(1);
+    }
+    let max_parallelism = args.max_parallelism.unwrap_or(8);
+    let repetitions = args.repetitions.unwrap_or(3);
+    let part_sizes = if args.part_sizes.is_empty() {
+        vec![MIN_PART_SIZE, 16 * 1024 * 1024, 64 * 1024 * 1024]
+    } else {
+        args.part_sizes
+    };
+    if part_sizes
+        .iter()
+        .any(|part_size| *part_size < MIN_PART_SIZE)
+    {
+        log::warn!(
+            "Part sizes below {} bytes are rejected by S3 for multi-part objects",
+            MIN_PART_SIZE
+        );
+    }
+
+    let (store, base_path) = args.store.build();
+    let timeline = args.timeline.start();
+    let store = timeline.wrap(store);
+    let namespace = RunNamespace::new(&base_path, args.run_id);
+    println!("Run id: {}", namespace.run_id);
+
+    let mut results = RunResults::new(
+        "put_crossover",
+        &namespace.run_id,
+        namespace.prefix.as_ref(),
+    );
+    args.store.record(&mut results);
+
+    // Sizes where the fastest multipart upload beat a single put, by median latency
+    let mut multipart_wins = Vec::new();
+    let mut size = min_size;
+    while size <= max_size {
+        let data = random_bytes(size);
+
+        let mut put_latencies = Vec::with_capacity(repetitions as usize);
+        let path = namespace.path(&format!("crossover/{}/put", size));
+        for _ in 0..repetitions {
+            let start = Instant::now();
+            if let Err(e) = store.put(&path, PutPayload::from_bytes(data.clone())).await {
+                eprintln!("Put of {} failed: {}", path, e);
+                std::process::exit(1);
+            }
+            put_latencies.push(start.elapsed().as_secs_f64());
+        }
+        let put_summary = LatencySummary::from_samples(&mut put_latencies).unwrap();
+        println!(
+            "size={} put p50={:?} seconds ({} MiB/s)",
+            size,
+            put_summary.p50,
+            size as f64 / put_summary.p50 / (1024.0 * 1024.0)
+        );
+
+        let mut best_multipart: Option<(u64, f64)> = None;
+        let mut multipart_results = Vec::with_capacity(part_sizes.len());
+        for &part_size in &part_sizes {
+            let mut latencies = Vec::with_capacity(repetitions as usize);
+            let path = namespace.path(&format!("crossover/{}/multipart-{}", size, part_size));
+            for _ in 0..repetitions {
+                let start = Instant::now();
+                if let Err(e) = upload_in_parts(
+                    store.as_ref(),
+                    &path,
+                    data.clone(),
+                    part_size as usize,
+                    max_parallelism,
+                )
+                .await
+                {
+                    eprintln!("Multipart upload failed and was aborted: {}", e);
+                    std::process::exit(1);
+                }
+                latencies.push(start.elapsed().as_secs_f64());
+            }
+            let summary = LatencySummary::from_samples(&mut latencies).unwrap();
+            println!(
+                "size={} multipart part_size={} p50={:?} seconds ({} MiB/s)",
+                size,
+                part_size,
+                summary.p50,
+                size as f64 / summary.p50 / (1024.0 * 1024.0)
+            );
+            if best_multipart.is_none_or(|(_, best)| summary.p50 < best) {
+                best_multipart = Some((part_size, summary.p50));
+            }
+            multipart_results.push((part_size, summary));
+        }
+
+        if let Some((part_size, p50)) = best_multipart {
+            if p50 < put_summary.p50 {
+                multipart_wins.push((size, part_size));
+            }
+        }
+
+        results.iterations.push(IterationResult {
+            label: Some("put".to_string()),
+            object_size: Some(size),
+            elapsed_secs: put_summary.mean * put_summary.count as f64,
+            num_requests: repetitions as u64,
+            num_bytes: size * repetitions as u64,
+            latency: Some(put_summary),
+            ..Default::default()
+        });
+        for (part_size, summary) in multipart_results {
+            results.iterations.push(IterationResult {
+                label: Some(format!("multipart-{}", part_size)),
+                object_size: Some(size),
+                elapsed_secs: summary.mean * summary.count as f64,
+                num_requests: repetitions as u64,
+                num_bytes: size * repetitions as u64,
+                latency: Some(summary),
+                ..Default::default()
+            });
+        }
+
+        size *= size_factor;
+    }
+
+    // The crossover is the smallest size from which multipart wins for every larger size too,
+    // so a single noisy measurement at a small size doesn't count
+    let mut crossover = None;
+    let mut tested_size = max_size_tested(min_size, max_size, size_factor);
+    for &(size, part_size) in multipart_wins.iter().rev() {
+        if size != tested_size {
+            break;
+        }
+        crossover = Some((size, part_size));
+        tested_size /= size_factor;
+    }
+    match crossover {
+        Some((size, part_size)) => {
+            println!(
+                "Crossover: multipart (part size {}) is faster than put from {} bytes",
+                part_size, size
+            );
+            results
+                .summary
+                .insert("crossover_size".to_string(), size.into());
+            results
+                .summary
+                .insert("crossover_part_size".to_string(), part_size.into());
+        }
+        None => println!("Crossover: put was at least as fast as multipart for every size tested"),
+    }
+
+    timeline.finish(&mut results);
+    if let Some(results_path) = args.results {
+        results.write(results_path);
+    }
+
+    if args.cleanup {
+        let num_deleted = namespace.cleanup(store.as_ref()).await;
+        println!("Deleted {} objects under {}", num_deleted, namespace.prefix);
+    }
+}
+
+fn max_size_tested(min_size: u64, max_size: u64, size_factor: u64) -> u64 {
+    let mut size = min_size;
+    while size * size_factor <= max_size {
+        size *= size_factor;
+    }
+    size
+}
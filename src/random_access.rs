@@ -1,176 +1,261 @@
-use std::sync::{Arc, Mutex};
-
 use clap::Parser;
-use futures::StreamExt;
-use object_store::{
-    aws::AmazonS3Builder, gcp::GoogleCloudStorageBuilder, local::LocalFileSystem, memory::InMemory,
-    path::Path, ObjectStore, PutPayload,
+use object_store::path::Path;
+use object_store_bench::{
+    arrivals::Arrivals,
+    errors::ErrorArgs,
+    hedge::HedgeArgs,
+    limiter::LimiterArgs,
+    namespace::RunNamespace,
+    random_reads::{run_iteration, Load, RandomReadArgs},
+    results::{IterationResult, RunResults},
+    stats::LatencySummary,
+    store::StoreArgs,
+    timeline::TimelineArgs,
+    timing::TimingArgs,
+    tuning::{ConcurrencyRamp, RampStep},
 };
-use rand::prelude::SliceRandom;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
-    #[arg(long)]
-    num_files: Option<u64>,
+    #[command(flatten)]
+    reads: RandomReadArgs,
 
-    #[arg(long)]
-    num_rows: Option<u64>,
+    #[command(flatten)]
+    store: StoreArgs,
 
-    #[arg(long)]
-    bytes_per_row: Option<u64>,
+    #[command(flatten)]
+    timeline: TimelineArgs,
 
+    /// Read existing files instead of writing them: the ones at --path, or the ones written by
+    /// the run given with --run-id
     #[arg(long)]
-    max_concurrent_reads: Option<u64>,
+    skip_upload: bool,
 
+    /// Where the files live, defaults to rab_files
     #[arg(long)]
-    access_key: Option<String>,
+    path: Option<String>,
 
     #[arg(long)]
-    secret_key: Option<String>,
+    num_iterations: Option<u32>,
 
-    #[arg(long)]
-    skip_upload: bool,
+    #[command(flatten)]
+    timing: TimingArgs,
 
+    /// Reuse the namespace of an earlier run instead of creating a new one
     #[arg(long)]
-    base_uri: String,
+    run_id: Option<String>,
 
+    /// Delete everything written under the run's namespace once the run finishes
     #[arg(long)]
-    path: Option<String>,
+    cleanup: bool,
 
+    /// Write machine readable results to this file
     #[arg(long)]
-    num_iterations: Option<u32>,
+    results: Option<String>,
 
+    /// Instead of running fixed iterations, ramp the number of concurrent reads up to
+    /// --max-concurrent-reads and report the knee where throughput stops increasing
     #[arg(long)]
-    takes_per_iter: Option<u32>,
+    auto_tune: bool,
+
+    /// Run open-loop, issuing reads at `constant:<iops>` or `poisson:<iops>` however many are in
+    /// flight, with latency measured from when each read should have started
+    #[arg(long, conflicts_with = "auto_tune")]
+    rate: Option<Arrivals>,
+
+    #[command(flatten)]
+    errors: ErrorArgs,
+
+    #[command(flatten)]
+    hedge: HedgeArgs,
+
+    #[command(flatten)]
+    limiter: LimiterArgs,
 }
 
 #[tokio::main]
 async fn main() {
+    object_store_bench::retries::init_logging();
+
     let args = Args::parse();
 
     let num_iterations = args.num_iterations.unwrap_or(5);
 
-    let num_files = args.num_files.unwrap_or(10);
-    let num_rows = args.num_rows.unwrap_or(1000000);
-    let bytes_per_row = args.bytes_per_row.unwrap_or(8);
-    let max_concurrent_reads = args.max_concurrent_reads.unwrap_or(10000);
-    let takes_per_iter = args.takes_per_iter.unwrap_or(10000);
-
-    let mut base_path = "".to_string();
-    let base_path_ref = &mut base_path;
-    let make_store = move || {
-        if args.base_uri.starts_with("s3://") {
-            let mut store = AmazonS3Builder::new()
-                .with_bucket_name(args.base_uri[5..].to_string())
-                .with_region("us-east-1");
-            std::mem::swap(base_path_ref, &mut args.base_uri[5..].to_string());
-            if let Some(access_key) = args.access_key.clone() {
-                store = store.with_access_key_id(access_key);
-            }
-            if let Some(secret_key) = args.secret_key.clone() {
-                store = store.with_secret_access_key(secret_key);
-            }
-            Arc::new(store.build().unwrap()) as Arc<dyn ObjectStore>
-        } else if args.base_uri.starts_with("gs://") {
-            let store = GoogleCloudStorageBuilder::new()
-                .with_url(args.base_uri)
-                .build()
-                .unwrap();
-            Arc::new(store) as Arc<dyn ObjectStore>
-        } else if args.base_uri == "memory" {
-            Arc::new(InMemory::new()) as Arc<dyn ObjectStore>
-        } else {
-            Arc::new(LocalFileSystem::new_with_prefix(args.base_uri).unwrap())
-        }
-    };
+    let max_concurrent_reads = args.reads.max_concurrent_reads();
+    let takes_per_iter = args.reads.takes_per_iter();
 
-    let store = make_store();
-    let mut rows_per_file = num_rows / num_files;
-    if num_rows % num_files != 0 {
-        rows_per_file += 1;
+    if let Err(e) = args.reads.validate() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let mut limiter = args.limiter.limiter(max_concurrent_reads as usize);
+    if limiter.is_some() && (args.auto_tune || args.rate.is_some()) {
+        eprintln!("--scheduler only applies to closed-loop runs without --auto-tune");
+        std::process::exit(1);
     }
-    println!("Num rows: {}", num_rows);
-    println!("Num files: {}", num_files);
-    println!("Rows per file: {}", rows_per_file);
 
-    let path = Path::from(format!("{}/{}", base_path, "rab_files"));
+    let (store, base_path) = args.store.build();
+    let timeline = args.timeline.start();
+    let store = timeline.wrap(store);
+    // Without a --run-id, --skip-upload reads files that already exist at --path rather than
+    // ones an earlier run wrote into its namespace
+    let existing_files = args.skip_upload && args.run_id.is_none();
+    let namespace = RunNamespace::new(&base_path, args.run_id);
+    println!("Run id: {}", namespace.run_id);
 
-    println!("Path: {}", path);
+    let path = args.path.unwrap_or("rab_files".to_string());
+    let path = if existing_files {
+        base_path
+            .parts()
+            .chain(Path::from(path.as_str()).parts())
+            .collect()
+    } else {
+        namespace.path(&path)
+    };
+    let file_layout = args.reads.layout(path);
+    println!("Num rows: {}", file_layout.num_rows);
+    println!("Num files: {}", file_layout.num_files);
+    println!("Rows per file: {}", file_layout.rows_per_file);
+    println!("Path: {}", file_layout.path);
 
     if !args.skip_upload {
-        for file_idx in 0..num_files {
-            let offset = file_idx * rows_per_file;
-            let capacity = num_rows - offset;
-            let rows_this_file = rows_per_file.min(capacity);
-            let upload_size = rows_this_file as usize * bytes_per_row as usize;
-            let mut data = bytes::BytesMut::with_capacity(upload_size);
-            unsafe { data.set_len(upload_size) };
-            let data = data.freeze();
-            let path = path.child(file_idx.to_string());
-            let mut multipart = store.put_multipart(&path).await.unwrap();
-            let start = std::time::Instant::now();
-            println!("About to upload {} bytes of data", data.len());
-            multipart
-                .put_part(PutPayload::from_bytes(data.clone()))
-                .await
-                .unwrap();
-            multipart.complete().await.unwrap();
-            println!("Upload took {:?} seconds", start.elapsed().as_secs_f64());
+        if let Err(e) = file_layout.write_files(store.as_ref()).await {
+            eprintln!(
+                "Upload of {} failed and was aborted: {}",
+                file_layout.path, e
+            );
+            std::process::exit(1);
         }
     }
 
-    for _ in 0..num_iterations {
-        let mut row_ids = (0..num_rows).collect::<Vec<_>>();
-        let latencies = Arc::new(Mutex::new(Vec::new()));
-        row_ids.shuffle(&mut rand::thread_rng());
-        let mut read_tasks = Vec::with_capacity(num_rows as usize);
-        for task_id in 0..takes_per_iter {
-            let path = path.clone();
-            let addr = row_ids[task_id as usize];
-            let file_id = addr / rows_per_file;
-            let file_offset = (addr % rows_per_file) * bytes_per_row;
-            let read_end = file_offset + bytes_per_row;
-            let store = store.clone();
-            let path = path.child(file_id.to_string());
-            let latencies = latencies.clone();
-
-            read_tasks.push(async move {
-                let start = std::time::Instant::now();
-                let store = store.clone();
-                store
-                    .get_range(&path, file_offset as usize..read_end as usize)
-                    .await
-                    .unwrap();
-                latencies
-                    .lock()
-                    .unwrap()
-                    .push(start.elapsed().as_secs_f64());
-                // println!("Download took {:?} seconds", start.elapsed().as_secs_f64());
+    let mut results = RunResults::new(
+        "random_access",
+        &namespace.run_id,
+        namespace.prefix.as_ref(),
+    );
+    args.store.record(&mut results);
+    let hedger = args.hedge.hedger();
+    let mut stopped_early = false;
+    if args.auto_tune {
+        let mut ramp = ConcurrencyRamp::new(1, max_concurrent_reads as usize);
+        while let Some(concurrency) = ramp.next_concurrency() {
+            let mut outcome = run_iteration(
+                &store,
+                &file_layout,
+                takes_per_iter,
+                Load::Closed(concurrency),
+                hedger.as_ref(),
+            )
+            .await;
+            if args.errors.include_failed_latencies {
+                outcome.include_failed_latencies();
+            }
+            let latency = LatencySummary::from_samples(&mut outcome.latencies);
+            // Failed reads don't count towards throughput, and if they all failed the latency
+            // is as bad as it gets
+            let num_succeeded = outcome.num_requests() - outcome.errors.total();
+            let iops_per_second = num_succeeded as f64 / outcome.elapsed_secs;
+            let p99 = latency.as_ref().map_or(f64::INFINITY, |l| l.p99);
+            println!(
+                "Concurrency {}: {} iops/s p99={:?} seconds{}",
+                concurrency,
+                iops_per_second,
+                p99,
+                outcome.errors.suffix()
+            );
+            ramp.record(RampStep {
+                concurrency,
+                throughput: iops_per_second,
+                p99,
             });
+            stopped_early =
+                args.errors
+                    .should_stop(&outcome.errors, outcome.num_requests(), &mut results);
+            results.iterations.push(IterationResult {
+                concurrency: Some(concurrency),
+                elapsed_secs: outcome.elapsed_secs,
+                num_requests: outcome.num_requests(),
+                num_bytes: outcome.num_bytes,
+                latency,
+                errors: outcome.errors.to_map(),
+                ..Default::default()
+            });
+            if stopped_early {
+                break;
+            }
         }
+        ramp.report("iops/s", &mut results);
+    } else {
+        let mut schedule = args.timing.schedule(num_iterations);
+        while schedule.next_iteration() {
+            let load = match (args.rate, &mut limiter) {
+                (Some(arrivals), _) => Load::Open(arrivals),
+                (None, Some(limiter)) => Load::Adaptive(limiter),
+                (None, None) => Load::Closed(max_concurrent_reads as usize),
+            };
+            let mut outcome =
+                run_iteration(&store, &file_layout, takes_per_iter, load, hedger.as_ref()).await;
+            if args.errors.include_failed_latencies {
+                outcome.include_failed_latencies();
+            }
+            let total_elapsed = outcome.elapsed_secs;
+            let latency = LatencySummary::from_samples(&mut outcome.latencies);
+            let p50 = latency.as_ref().map_or(f64::NAN, |l| l.p50);
 
-        let total_start = std::time::Instant::now();
-        futures::stream::iter(read_tasks)
-            .buffer_unordered(max_concurrent_reads as usize)
-            .collect::<Vec<_>>()
-            .await;
-        let total_elapsed = total_start.elapsed();
-
-        let mut latencies = latencies.lock().unwrap();
-        latencies.sort_by(|a, b| a.total_cmp(b));
-        let p50 = latencies[latencies.len() / 2];
-
-        let iops_per_second = (takes_per_iter as f64) / total_elapsed.as_secs_f64();
-        let gibps = (takes_per_iter as f64 * bytes_per_row as f64)
-            / total_elapsed.as_secs_f64()
-            / (1024.0 * 1024.0 * 1024.0);
-        println!(
-            "Total download took {:?} seconds ({} iops/s {} GiB/s {} p50 latency)",
-            total_elapsed.as_secs_f64(),
-            iops_per_second,
-            gibps,
-            p50,
-        );
+            let num_succeeded = outcome.num_requests() - outcome.errors.total();
+            let iops_per_second = num_succeeded as f64 / total_elapsed;
+            let gibps = outcome.num_bytes as f64 / total_elapsed / (1024.0 * 1024.0 * 1024.0);
+            println!(
+                "Total download took {:?} seconds ({} iops/s{} {} GiB/s {} p50 latency{})",
+                total_elapsed,
+                iops_per_second,
+                args.rate
+                    .map(|arrivals| format!(" of {} target", arrivals.rate()))
+                    .unwrap_or_default(),
+                gibps,
+                p50,
+                outcome.errors.suffix(),
+            );
+            stopped_early =
+                args.errors
+                    .should_stop(&outcome.errors, outcome.num_requests(), &mut results);
+            let iteration = IterationResult {
+                // Where the adaptive limit ended up
+                concurrency: limiter.as_ref().map(|limiter| limiter.limit()),
+                target_rate: args.rate.map(|arrivals| arrivals.rate()),
+                elapsed_secs: total_elapsed,
+                num_requests: outcome.num_requests(),
+                num_bytes: outcome.num_bytes,
+                latency,
+                errors: outcome.errors.to_map(),
+                ..Default::default()
+            };
+            schedule.record(vec![iteration], &mut results);
+            if stopped_early {
+                break;
+            }
+        }
+        schedule.finish(&mut results);
+    }
+
+    if let Some(hedger) = &hedger {
+        hedger.report(&mut results).await;
+    }
+    if let Some(limiter) = &limiter {
+        limiter.report(&mut results);
+    }
+    timeline.finish(&mut results);
+    if let Some(results_path) = args.results {
+        results.write(results_path);
+    }
+
+    if args.cleanup {
+        let num_deleted = namespace.cleanup(store.as_ref()).await;
+        println!("Deleted {} objects under {}", num_deleted, namespace.prefix);
+    }
+
+    if stopped_early {
+        std::process::exit(1);
     }
 }
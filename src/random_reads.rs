@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use object_store::{path::Path, ObjectStore, PutPayload};
+use rand::prelude::SliceRandom;
+use serde::Deserialize;
+
+use crate::{
+    arrivals::Arrivals,
+    hedge::Hedger,
+    limiter::ConcurrencyLimiter,
+    multipart::{run_or_abort, SharedUpload, UploadError},
+    phase::{run_phase, run_phase_limited, run_phase_open_loop, PhaseOutcome},
+};
+
+/// Options for spreading fixed size rows over files and reading random rows back
+#[derive(clap::Args, Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RandomReadArgs {
+    /// Number of files the rows are spread over, defaults to 10
+    #[arg(long)]
+    pub num_files: Option<u64>,
+
+    /// Defaults to 1000000
+    #[arg(long)]
+    pub num_rows: Option<u64>,
+
+    /// Defaults to 8
+    #[arg(long)]
+    pub bytes_per_row: Option<u64>,
+
+    /// Reads in flight, defaults to 10000
+    #[arg(long)]
+    pub max_concurrent_reads: Option<u64>,
+
+    /// Rows read per iteration, defaults to 10000
+    #[arg(long)]
+    pub takes_per_iter: Option<u32>,
+}
+
+impl RandomReadArgs {
+    /// Where the rows live, under `path`
+    pub fn layout(&self, path: Path) -> FileLayout {
+        let num_files = self.num_files.unwrap_or(10);
+        let num_rows = self.num_rows.unwrap_or(1000000);
+        let rows_per_file = num_rows.div_ceil(num_files);
+        FileLayout {
+            path,
+            num_files,
+            num_rows,
+            rows_per_file,
+            bytes_per_row: self.bytes_per_row.unwrap_or(8),
+        }
+    }
+
+    /// Checks the options describe rows that exist.  Each iteration reads distinct rows, so it
+    /// can't take more than there are.
+    pub fn validate(&self) -> Result<(), String> {
+        let num_rows = self.num_rows.unwrap_or(1000000);
+        if self.num_files == Some(0) || num_rows == 0 {
+            return Err("num_files and num_rows must be at least 1".to_string());
+        }
+        if self.takes_per_iter() as u64 > num_rows {
+            return Err(format!(
+                "takes_per_iter ({}) can't be more than num_rows ({})",
+                self.takes_per_iter(),
+                num_rows
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn max_concurrent_reads(&self) -> u64 {
+        self.max_concurrent_reads.unwrap_or(10000)
+    }
+
+    pub fn takes_per_iter(&self) -> u32 {
+        self.takes_per_iter.unwrap_or(10000)
+    }
+}
+
+/// Where the rows live
+#[derive(Debug, Clone)]
+pub struct FileLayout {
+    pub path: Path,
+    pub num_files: u64,
+    pub num_rows: u64,
+    pub rows_per_file: u64,
+    pub bytes_per_row: u64,
+}
+
+impl FileLayout {
+    /// Writes every file, each in a single part upload
+    pub async fn write_files(&self, store: &dyn ObjectStore) -> Result<(), UploadError> {
+        for file_idx in 0..self.num_files {
+            let offset = file_idx * self.rows_per_file;
+            let capacity = self.num_rows - offset;
+            let rows_this_file = self.rows_per_file.min(capacity);
+            let upload_size = rows_this_file as usize * self.bytes_per_row as usize;
+            let mut data = bytes::BytesMut::with_capacity(upload_size);
+            unsafe { data.set_len(upload_size) };
+            let data = data.freeze();
+            let path = self.path.child(file_idx.to_string());
+            let multipart = SharedUpload::new(store.put_multipart(&path).await?);
+            let start = std::time::Instant::now();
+            println!("About to upload {} bytes of data", data.len());
+            let upload = async {
+                multipart
+                    .put_part(PutPayload::from_bytes(data.clone()))
+                    .await?;
+                multipart.complete().await
+            };
+            run_or_abort(&multipart, upload).await?;
+            println!("Upload took {:?} seconds", start.elapsed().as_secs_f64());
+        }
+        Ok(())
+    }
+}
+
+/// How reads are issued
+pub enum Load<'a> {
+    /// Keep this many reads in flight
+    Closed(usize),
+    /// Start reads on a schedule, however many are in flight
+    Open(Arrivals),
+    /// Keep as many reads in flight as the limiter allows
+    Adaptive(&'a mut ConcurrencyLimiter),
+}
+
+/// Reads `takes_per_iter` random rows
+pub async fn run_iteration(
+    store: &Arc<dyn ObjectStore>,
+    layout: &FileLayout,
+    takes_per_iter: u32,
+    load: Load<'_>,
+    hedger: Option<&Arc<Hedger>>,
+) -> PhaseOutcome {
+    let mut row_ids = (0..layout.num_rows).collect::<Vec<_>>();
+    row_ids.shuffle(&mut rand::thread_rng());
+    let read = |task_id: u64| {
+        let addr = row_ids[task_id as usize];
+        let file_id = addr / layout.rows_per_file;
+        let file_offset = (addr % layout.rows_per_file) * layout.bytes_per_row;
+        let read_end = file_offset + layout.bytes_per_row;
+        let store = store.clone();
+        let path = layout.path.child(file_id.to_string());
+        let hedger = hedger.cloned();
+
+        async move {
+            let range = file_offset as usize..read_end as usize;
+            let bytes = match hedger {
+                Some(hedger) => hedger.get_range(&store, &path, range).await,
+                None => store.get_range(&path, range).await,
+            };
+            bytes.map(|bytes| bytes.len() as u64)
+        }
+    };
+
+    let outcome = match load {
+        Load::Closed(concurrency) => run_phase(takes_per_iter as u64, concurrency, read).await,
+        Load::Open(arrivals) => run_phase_open_loop(takes_per_iter as u64, arrivals, read).await,
+        Load::Adaptive(limiter) => run_phase_limited(takes_per_iter as u64, limiter, read).await,
+    };
+    if let Some(hedger) = hedger {
+        hedger.finish_stragglers().await;
+    }
+    outcome
+}
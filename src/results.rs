@@ -0,0 +1,130 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    retries::RetryTotals,
+    stats::{LatencyHistogram, LatencySummary},
+    timeline::TimelinePoint,
+};
+
+/// Machine readable results of a benchmark run, written with `--results <file>`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunResults {
+    pub workload: String,
+    pub run_id: String,
+    pub prefix: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_rss_bytes: Option<u64>,
+    pub iterations: Vec<IterationResult>,
+    /// Workload specific conclusions drawn from the iterations (e.g. a crossover point)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub summary: BTreeMap<String, serde_json::Value>,
+    /// Requests per interval across the whole run, see `--timeline-interval`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timeline: Vec<TimelinePoint>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IterationResult {
+    /// Which operation or phase this iteration measured, for workloads with more than one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub object_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+    /// Requests per second an open-loop iteration tried to issue
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_rate: Option<f64>,
+    pub elapsed_secs: f64,
+    pub num_requests: u64,
+    pub num_bytes: u64,
+    /// Number of objects returned or touched, for operations where that differs from requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_objects: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<LatencySummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub histogram: Option<LatencyHistogram>,
+    /// Failed requests by error class
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, u64>,
+    /// Requests given up on after the per-request timeout, when there was one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timed_out: Option<u64>,
+    /// Requests cancelled, or never started, when the iteration deadline passed, when there
+    /// was one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancelled: Option<u64>,
+    /// Retries the client made during the iteration, shared by every result of the iteration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<RetryTotals>,
+}
+
+impl RunResults {
+    pub fn new(workload: &str, run_id: &str, prefix: &str) -> Self {
+        Self {
+            workload: workload.to_string(),
+            run_id: run_id.to_string(),
+            prefix: prefix.to_string(),
+            peak_rss_bytes: None,
+            iterations: Vec::new(),
+            summary: BTreeMap::new(),
+            timeline: Vec::new(),
+        }
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) {
+        let file = std::fs::File::create(path).unwrap();
+        serde_json::to_writer_pretty(file, self).unwrap();
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+        serde_json::from_reader(file)
+            .map_err(|e| format!("invalid results in {}: {}", path.display(), e))
+    }
+
+    /// Totals across every iteration, with the latency percentiles averaged over the
+    /// iterations that recorded them
+    pub fn totals(&self) -> RunTotals {
+        let latencies = self
+            .iterations
+            .iter()
+            .filter_map(|iteration| iteration.latency.as_ref())
+            .collect::<Vec<_>>();
+        let mean = |pick: fn(&LatencySummary) -> f64| {
+            (!latencies.is_empty())
+                .then(|| latencies.iter().map(|l| pick(l)).sum::<f64>() / latencies.len() as f64)
+        };
+        RunTotals {
+            elapsed_secs: self.iterations.iter().map(|i| i.elapsed_secs).sum(),
+            num_requests: self.iterations.iter().map(|i| i.num_requests).sum(),
+            num_bytes: self.iterations.iter().map(|i| i.num_bytes).sum(),
+            p50: mean(|l| l.p50),
+            p99: mean(|l| l.p99),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RunTotals {
+    pub elapsed_secs: f64,
+    pub num_requests: u64,
+    pub num_bytes: u64,
+    pub p50: Option<f64>,
+    pub p99: Option<f64>,
+}
+
+impl RunTotals {
+    pub fn ops_per_sec(&self) -> f64 {
+        self.num_requests as f64 / self.elapsed_secs
+    }
+
+    pub fn mib_per_sec(&self) -> f64 {
+        self.num_bytes as f64 / self.elapsed_secs / (1024.0 * 1024.0)
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    cell::Cell,
+    fmt,
+    future::Future,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+
+use crate::results::RunResults;
+
+/// object_store only reports retries by logging them from here, through `tracing`'s log
+/// compatibility
+const RETRY_TARGET: &str = "object_store::client::retry";
+
+static COUNTERS: Counters = Counters {
+    retries: AtomicU64::new(0),
+    throttled_retries: AtomicU64::new(0),
+    transport_retries: AtomicU64::new(0),
+    backoff_micros: AtomicU64::new(0),
+    requests_retried: AtomicU64::new(0),
+    max_retries_per_request: AtomicU64::new(0),
+};
+
+thread_local! {
+    /// Retries of the request being polled on this thread, if it is being tracked
+    static CURRENT: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+struct Counters {
+    retries: AtomicU64,
+    throttled_retries: AtomicU64,
+    transport_retries: AtomicU64,
+    backoff_micros: AtomicU64,
+    requests_retried: AtomicU64,
+    max_retries_per_request: AtomicU64,
+}
+
+/// Retries the store's client made so far in the run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RetryTotals {
+    pub retries: u64,
+    /// Retries of 503 slow down responses
+    pub throttled_retries: u64,
+    /// Retries after the connection failed rather than the server returning an error
+    pub transport_retries: u64,
+    /// Time spent sleeping before retrying
+    pub backoff_secs: f64,
+    /// Requests made through a timeline store that needed at least one retry
+    pub requests_retried: u64,
+    pub max_retries_per_request: u64,
+}
+
+impl RetryTotals {
+    /// The totals so far
+    pub fn now() -> Self {
+        Self {
+            retries: COUNTERS.retries.load(Ordering::Relaxed),
+            throttled_retries: COUNTERS.throttled_retries.load(Ordering::Relaxed),
+            transport_retries: COUNTERS.transport_retries.load(Ordering::Relaxed),
+            backoff_secs: COUNTERS.backoff_micros.load(Ordering::Relaxed) as f64 / 1e6,
+            requests_retried: COUNTERS.requests_retried.load(Ordering::Relaxed),
+            max_retries_per_request: COUNTERS.max_retries_per_request.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The retries made since `earlier` was taken.  The per request maximum can't be split up
+    /// like that, so it stays the highest of the run so far.
+    pub fn since(&self, earlier: &RetryTotals) -> RetryTotals {
+        RetryTotals {
+            retries: self.retries - earlier.retries,
+            throttled_retries: self.throttled_retries - earlier.throttled_retries,
+            transport_retries: self.transport_retries - earlier.transport_retries,
+            backoff_secs: self.backoff_secs - earlier.backoff_secs,
+            requests_retried: self.requests_retried - earlier.requests_retried,
+            max_retries_per_request: self.max_retries_per_request,
+        }
+    }
+
+    /// Prints the totals, if there were any retries, and records them in the run's summary
+    pub fn report(&self, results: &mut RunResults) {
+        if self.retries > 0 {
+            println!("Retries: {}", self);
+        }
+        results
+            .summary
+            .insert("retries".to_string(), serde_json::to_value(self).unwrap());
+    }
+}
+
+impl fmt::Display for RetryTotals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({} throttled, {} transport) backing off for {:?} seconds, {} requests retried, \
+             at most {} times",
+            self.retries,
+            self.throttled_retries,
+            self.transport_retries,
+            self.backoff_secs,
+            self.requests_retried,
+            self.max_retries_per_request
+        )
+    }
+}
+
+/// Polls `fut`, attributing the retries logged meanwhile to it as one request
+pub async fn attribute<F: Future>(fut: F) -> F::Output {
+    let mut fut = std::pin::pin!(fut);
+    let mut retries = 0;
+    let output = std::future::poll_fn(|cx| {
+        let outer = CURRENT.replace(Some(0));
+        let poll = fut.as_mut().poll(cx);
+        retries += CURRENT.replace(outer).unwrap_or(0);
+        poll
+    })
+    .await;
+    if retries > 0 {
+        COUNTERS.requests_retried.fetch_add(1, Ordering::Relaxed);
+        COUNTERS
+            .max_retries_per_request
+            .fetch_max(retries, Ordering::Relaxed);
+    }
+    output
+}
+
+/// Sets up `env_logger` as usual, but also counts the retries object_store logs whatever the
+/// log level
+pub fn init_logging() {
+    let inner = env_logger::Builder::from_default_env().build();
+    let max_level = inner.filter().max(LevelFilter::Info);
+    log::set_boxed_logger(Box::new(RetryLogger { inner })).expect("logger already set");
+    log::set_max_level(max_level);
+}
+
+struct RetryLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for RetryLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target() == RETRY_TARGET || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.target() == RETRY_TARGET {
+            count_retry(&record.args().to_string());
+        }
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Counts a line like "Encountered server error, backing off for 0.1 seconds, retry 1 of 10:
+/// HTTP status server error (503 Service Unavailable) for url (...)"
+fn count_retry(message: &str) {
+    let Some((_, rest)) = message.split_once("backing off for ") else {
+        return;
+    };
+    let Some((backoff, rest)) = rest.split_once(" seconds, retry ") else {
+        return;
+    };
+    COUNTERS.retries.fetch_add(1, Ordering::Relaxed);
+    if let Ok(backoff) = backoff.parse::<f64>() {
+        COUNTERS
+            .backoff_micros
+            .fetch_add((backoff * 1e6) as u64, Ordering::Relaxed);
+    }
+    if message.starts_with("Encountered transport error") {
+        COUNTERS.transport_retries.fetch_add(1, Ordering::Relaxed);
+    } else if rest.contains("(503 ") || rest.contains("SlowDown") {
+        COUNTERS.throttled_retries.fetch_add(1, Ordering::Relaxed);
+    }
+    CURRENT.with(|current| {
+        if let Some(retries) = current.get() {
+            current.set(Some(retries + 1));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_retry(logger: &RetryLogger, message: &str) {
+        logger.log(
+            &Record::builder()
+                .target(RETRY_TARGET)
+                .level(log::Level::Info)
+                .args(format_args!("{}", message))
+                .build(),
+        );
+    }
+
+    #[tokio::test]
+    async fn counts_logged_retries() {
+        let logger = RetryLogger {
+            inner: env_logger::Builder::new().build(),
+        };
+        let before = RetryTotals::now();
+        attribute(async {
+            log_retry(
+                &logger,
+                "Encountered server error, backing off for 0.1 seconds, retry 1 of 10: HTTP \
+                 status server error (503 Service Unavailable) for url (http://bucket/key)",
+            );
+            log_retry(
+                &logger,
+                "Encountered transport error backing off for 0.25 seconds, retry 2 of 10: \
+                 error sending request",
+            );
+        })
+        .await;
+        log_retry(&logger, "Some other message from the retry module");
+
+        let retries = RetryTotals::now().since(&before);
+        assert_eq!(retries.retries, 2);
+        assert_eq!(retries.throttled_retries, 1);
+        assert_eq!(retries.transport_retries, 1);
+        assert!((retries.backoff_secs - 0.35).abs() < 1e-6);
+        assert_eq!(retries.requests_retried, 1);
+        assert!(retries.max_retries_per_request >= 2);
+    }
+}
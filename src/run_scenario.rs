@@ -0,0 +1,293 @@
+use clap::Parser;
+use object_store_bench::{
+    deadline::DeadlineArgs,
+    download::{self, Download},
+    errors::ErrorCounts,
+    large_upload::UploadArgs,
+    mix::{MixSpec, MixedWorkload},
+    multipart::UploadError,
+    namespace::RunNamespace,
+    random_reads::{self, FileLayout, Load},
+    results::{IterationResult, RunResults},
+    scenario::{PhaseKind, Scenario},
+};
+
+/// Runs a benchmark scenario described in a TOML file
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Path to the scenario file
+    scenario: String,
+}
+
+/// A phase along with the data it runs against
+enum Workload<'a> {
+    Mix(MixSpec),
+    Download(Download),
+    RandomAccess(FileLayout, u64, u32),
+    Upload(&'a UploadArgs),
+}
+
+#[tokio::main]
+async fn main() {
+    object_store_bench::retries::init_logging();
+
+    let args = Args::parse();
+
+    let scenario = match Scenario::from_file(&args.scenario) {
+        Ok(scenario) => scenario,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let (unwrapped_store, base_path) = scenario.store.build();
+    let timeline = scenario.timeline.start();
+    let store = timeline.wrap(unwrapped_store.clone());
+    let namespace = RunNamespace::new(&base_path, scenario.setup.run_id.clone());
+    println!(
+        "Running scenario {} ({})",
+        scenario.name.as_deref().unwrap_or(&args.scenario),
+        args.scenario
+    );
+    println!("Run id: {}", namespace.run_id);
+
+    let mut phases = Vec::with_capacity(scenario.phases.len());
+    for phase in &scenario.phases {
+        let workload = match phase.kind().unwrap() {
+            PhaseKind::Mix(mix) => Workload::Mix(mix),
+            PhaseKind::Download(args) => {
+                let mut download =
+                    args.download(namespace.path(&format!("download/{}", phase.name)));
+                download.total_size = if scenario.setup.skip {
+                    match store.head(&download.path).await {
+                        Ok(meta) => meta.size as u64,
+                        Err(e) => {
+                            eprintln!("Couldn't read {}: {}", download.path, e);
+                            std::process::exit(1);
+                        }
+                    }
+                } else {
+                    match download.upload(store.as_ref()).await {
+                        Ok(bytes_written) => bytes_written,
+                        Err(e) => {
+                            eprintln!("Upload failed and was aborted: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                };
+                Workload::Download(download)
+            }
+            PhaseKind::RandomAccess(args) => {
+                let layout = args.layout(namespace.path(&format!("random_access/{}", phase.name)));
+                if !scenario.setup.skip {
+                    if let Err(e) = layout.write_files(store.as_ref()).await {
+                        eprintln!("Upload of {} failed and was aborted: {}", layout.path, e);
+                        std::process::exit(1);
+                    }
+                }
+                Workload::RandomAccess(layout, args.max_concurrent_reads(), args.takes_per_iter())
+            }
+            PhaseKind::Upload(args) => Workload::Upload(args),
+        };
+        phases.push((phase, workload));
+    }
+
+    // Only the mixes run against the data objects
+    let mixes = phases
+        .iter()
+        .filter_map(|(_, workload)| match workload {
+            Workload::Mix(mix) => Some(mix),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let max_put_size = mixes
+        .iter()
+        .map(|mix| mix.max_put_size())
+        .max()
+        .unwrap_or(0);
+    let workload = MixedWorkload::new(
+        store.clone(),
+        namespace.path("mixed"),
+        scenario.setup.num_objects,
+        scenario.setup.object_size,
+        max_put_size,
+    );
+    let mut results = RunResults::new("scenario", &namespace.run_id, namespace.prefix.as_ref());
+    scenario.store.record(&mut results);
+    if let Some(name) = &scenario.name {
+        results
+            .summary
+            .insert("scenario".to_string(), name.as_str().into());
+    }
+    let mut stopped_early = false;
+    if !scenario.setup.skip && !mixes.is_empty() {
+        println!(
+            "Writing {} data objects of {} bytes",
+            scenario.setup.num_objects, scenario.setup.object_size
+        );
+        let populate = workload.populate(scenario.setup.concurrency).await;
+        let (errors, num_requests) = (populate.errors.clone(), populate.num_requests());
+        populate.report("populate");
+        stopped_early = scenario
+            .errors
+            .should_stop(&errors, num_requests, &mut results);
+    }
+
+    let mut schedule = scenario.timing.schedule(scenario.iterations);
+    let mut iteration_idx = 0;
+    while !stopped_early && schedule.next_iteration() {
+        let mut iteration = Vec::new();
+        for (phase, phase_workload) in &phases {
+            let (errors, num_requests) = match phase_workload {
+                Workload::Mix(mix) => {
+                    println!(
+                        "Iteration {} phase {}: {} ops of {}",
+                        iteration_idx, phase.name, phase.num_ops, mix
+                    );
+                    let mut outcome = match phase.rate {
+                        Some(arrivals) => {
+                            workload.run_open_loop(mix, phase.num_ops, arrivals).await
+                        }
+                        None => workload.run(mix, phase.num_ops, phase.concurrency).await,
+                    };
+                    if scenario.errors.include_failed_latencies {
+                        outcome.include_failed_latencies();
+                    }
+                    let (errors, num_requests) = (outcome.errors(), outcome.num_requests());
+                    iteration.extend(outcome.report().into_iter().map(|result| {
+                        IterationResult {
+                            label: result
+                                .label
+                                .as_ref()
+                                .map(|op| format!("{}/{}", phase.name, op)),
+                            concurrency: phase.rate.is_none().then_some(phase.concurrency),
+                            ..result
+                        }
+                    }));
+                    (errors, num_requests)
+                }
+                Workload::Download(download) => {
+                    println!(
+                        "Iteration {} phase {}: download {} bytes on {} clients",
+                        iteration_idx, phase.name, download.total_size, download.num_clients
+                    );
+                    let (outcome, _) = download::run_iteration(
+                        &|| timeline.wrap(scenario.store.another_client(&unwrapped_store)),
+                        download,
+                        download.threads_per_client as usize,
+                        scenario.errors.include_failed_latencies,
+                        &DeadlineArgs::default(),
+                        None,
+                        None,
+                    )
+                    .await;
+                    let (errors, num_requests) = (outcome.errors.clone(), outcome.num_requests());
+                    iteration.push(IterationResult {
+                        concurrency: Some(
+                            (download.num_clients * download.threads_per_client) as usize,
+                        ),
+                        ..outcome.report(&phase.name)
+                    });
+                    (errors, num_requests)
+                }
+                Workload::RandomAccess(layout, max_concurrent_reads, takes_per_iter) => {
+                    println!(
+                        "Iteration {} phase {}: {} random reads of {} bytes",
+                        iteration_idx, phase.name, takes_per_iter, layout.bytes_per_row
+                    );
+                    let load = match phase.rate {
+                        Some(arrivals) => Load::Open(arrivals),
+                        None => Load::Closed(*max_concurrent_reads as usize),
+                    };
+                    let mut outcome =
+                        random_reads::run_iteration(&store, layout, *takes_per_iter, load, None)
+                            .await;
+                    if scenario.errors.include_failed_latencies {
+                        outcome.include_failed_latencies();
+                    }
+                    let (errors, num_requests) = (outcome.errors.clone(), outcome.num_requests());
+                    iteration.push(IterationResult {
+                        concurrency: phase
+                            .rate
+                            .is_none()
+                            .then_some(*max_concurrent_reads as usize),
+                        ..outcome.report(&phase.name)
+                    });
+                    (errors, num_requests)
+                }
+                Workload::Upload(args) => {
+                    println!(
+                        "Iteration {} phase {}: upload {} bytes with {:?}",
+                        iteration_idx,
+                        phase.name,
+                        args.total_size(),
+                        args.mode
+                    );
+                    let path = namespace.path(&format!("upload/{}/{}", phase.name, iteration_idx));
+                    let start = std::time::Instant::now();
+                    let result = args.upload(store.clone(), &path, None).await;
+                    let elapsed_secs = start.elapsed().as_secs_f64();
+                    let mut errors = ErrorCounts::default();
+                    match result {
+                        Ok(stats) => {
+                            println!(
+                                "Total {} took {:?} seconds ({} MiB/s, {} parts of {} to {} bytes)",
+                                phase.name,
+                                elapsed_secs,
+                                stats.bytes_written as f64 / elapsed_secs / (1024.0 * 1024.0),
+                                stats.num_parts,
+                                stats.min_part_size,
+                                stats.max_part_size
+                            );
+                            iteration.push(IterationResult {
+                                label: Some(phase.name.clone()),
+                                concurrency: Some(args.max_parallelism() as usize),
+                                elapsed_secs,
+                                num_requests: stats.num_parts,
+                                num_bytes: stats.bytes_written,
+                                ..Default::default()
+                            });
+                        }
+                        Err(UploadError::Store(e)) => {
+                            eprintln!("Upload failed and was aborted: {}", e);
+                            errors.record(&e);
+                        }
+                        Err(e) => {
+                            eprintln!("Upload failed and was aborted: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                    (errors, 1)
+                }
+            };
+            stopped_early = scenario
+                .errors
+                .should_stop(&errors, num_requests, &mut results);
+            if stopped_early {
+                break;
+            }
+        }
+        schedule.record(iteration, &mut results);
+        if stopped_early {
+            break;
+        }
+        iteration_idx += 1;
+    }
+    schedule.finish(&mut results);
+
+    timeline.finish(&mut results);
+    if let Some(results_path) = &scenario.output.results {
+        results.write(results_path);
+    }
+
+    if scenario.output.cleanup {
+        let num_deleted = namespace.cleanup(store.as_ref()).await;
+        println!("Deleted {} objects under {}", num_deleted, namespace.prefix);
+    }
+
+    if stopped_early {
+        std::process::exit(1);
+    }
+}
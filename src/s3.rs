@@ -37,8 +37,7 @@ struct Args {
 
 async fn make_client() -> Client {
     let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
-    let client = aws_sdk_s3::Client::new(&config);
-    client
+    aws_sdk_s3::Client::new(&config)
 }
 
 #[tokio::main]
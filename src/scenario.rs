@@ -0,0 +1,175 @@
+use serde::Deserialize;
+
+use crate::{
+    arrivals::Arrivals,
+    download::DownloadArgs,
+    errors::ErrorArgs,
+    large_upload::UploadArgs,
+    mix::{MixSpec, OpSpec},
+    random_reads::RandomReadArgs,
+    store::StoreArgs,
+    timeline::TimelineArgs,
+    timing::TimingArgs,
+};
+
+/// A benchmark scenario described in a TOML file, see `scenarios/example.toml`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub name: Option<String>,
+    /// How many times to run all of the phases, unless `timing.duration` is set
+    #[serde(default = "default_iterations")]
+    pub iterations: u32,
+    #[serde(default)]
+    pub timing: TimingArgs,
+    #[serde(default)]
+    pub timeline: TimelineArgs,
+    #[serde(default)]
+    pub errors: ErrorArgs,
+    pub store: StoreArgs,
+    #[serde(default)]
+    pub setup: Setup,
+    pub phases: Vec<Phase>,
+    #[serde(default)]
+    pub output: Output,
+}
+
+/// The data objects written before the phases run
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Setup {
+    pub num_objects: u64,
+    pub object_size: u64,
+    pub concurrency: usize,
+    /// Reuse the data objects of an earlier run, requires `run_id`
+    pub skip: bool,
+    pub run_id: Option<String>,
+}
+
+impl Default for Setup {
+    fn default() -> Self {
+        Self {
+            num_objects: 1000,
+            object_size: 8 * 1024 * 1024,
+            concurrency: 64,
+            skip: false,
+            run_id: None,
+        }
+    }
+}
+
+/// One workload: either a mix of `ops` run against the data objects, or one of the
+/// `download`, `random_access` or `upload` tables, which take the options of the binary of the
+/// same name and write their own data
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Phase {
+    pub name: String,
+    #[serde(default)]
+    pub ops: Vec<OpSpec>,
+    #[serde(default = "default_num_ops")]
+    pub num_ops: u64,
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// Issue requests open-loop at `constant:<ops/s>` or `poisson:<ops/s>` instead of keeping
+    /// `concurrency` in flight.  Also applies to `random_access`.
+    #[serde(default)]
+    pub rate: Option<Arrivals>,
+    pub download: Option<DownloadArgs>,
+    pub random_access: Option<RandomReadArgs>,
+    pub upload: Option<UploadArgs>,
+}
+
+/// What a phase runs
+pub enum PhaseKind<'a> {
+    Mix(MixSpec),
+    Download(&'a DownloadArgs),
+    RandomAccess(&'a RandomReadArgs),
+    Upload(&'a UploadArgs),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Output {
+    /// Write machine readable results to this file
+    pub results: Option<String>,
+    /// Delete everything written under the run's namespace once the run finishes
+    #[serde(default)]
+    pub cleanup: bool,
+}
+
+fn default_iterations() -> u32 {
+    1
+}
+
+fn default_num_ops() -> u64 {
+    10000
+}
+
+fn default_concurrency() -> usize {
+    64
+}
+
+impl Scenario {
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        Self::from_toml(&contents).map_err(|e| format!("invalid scenario {}: {}", path, e))
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, String> {
+        let scenario = toml::from_str::<Scenario>(contents).map_err(|e| e.to_string())?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.phases.is_empty() {
+            return Err("at least one [[phases]] entry is required".to_string());
+        }
+        if self.setup.skip && self.setup.run_id.is_none() {
+            return Err(
+                "setup.skip requires setup.run_id of the run that wrote the data".to_string(),
+            );
+        }
+        if self.setup.num_objects == 0 {
+            return Err("setup.num_objects must be at least 1".to_string());
+        }
+        for phase in &self.phases {
+            phase
+                .kind()
+                .map_err(|e| format!("phase '{}': {}", phase.name, e))?;
+            if phase.concurrency == 0 {
+                return Err(format!(
+                    "phase '{}': concurrency must be at least 1",
+                    phase.name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Phase {
+    pub fn kind(&self) -> Result<PhaseKind<'_>, String> {
+        let kind = match (&self.download, &self.random_access, &self.upload) {
+            (None, None, None) => return MixSpec::new(self.ops.clone()).map(PhaseKind::Mix),
+            (Some(download), None, None) => PhaseKind::Download(download),
+            (None, Some(random_access), None) => {
+                random_access.validate()?;
+                PhaseKind::RandomAccess(random_access)
+            }
+            (None, None, Some(upload)) => PhaseKind::Upload(upload),
+            _ => {
+                return Err("only one of download, random_access and upload may be set".to_string())
+            }
+        };
+        if !self.ops.is_empty() {
+            return Err("ops only apply to a mix of operations".to_string());
+        }
+        if self.rate.is_some() && !matches!(kind, PhaseKind::RandomAccess(_)) {
+            return Err("rate only applies to ops and random_access".to_string());
+        }
+        Ok(kind)
+    }
+}
@@ -0,0 +1,182 @@
+use clap::Parser;
+use object_store::PutPayload;
+use object_store_bench::{
+    data::random_bytes,
+    distribution::SizeDistribution,
+    errors::{ErrorArgs, ErrorCounts},
+    namespace::RunNamespace,
+    phase::{run_phase, PhaseOutcome},
+    results::{IterationResult, RunResults},
+    store::StoreArgs,
+    timeline::TimelineArgs,
+    timing::TimingArgs,
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+/// Writes many small objects under a prefix and then reads them all back
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    store: StoreArgs,
+
+    #[command(flatten)]
+    timeline: TimelineArgs,
+
+    #[arg(long)]
+    num_objects: Option<u64>,
+
+    /// Object sizes, e.g. `fixed:4096`, `uniform:1024-65536` or `choice:1024,4096,16384`
+    #[arg(long)]
+    size_distribution: Option<SizeDistribution>,
+
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_concurrency: Option<usize>,
+
+    /// Seed for picking object sizes so runs are repeatable
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// How many times to write and read back every object, defaults to 1
+    #[arg(long)]
+    num_iterations: Option<u32>,
+
+    #[command(flatten)]
+    timing: TimingArgs,
+
+    #[command(flatten)]
+    errors: ErrorArgs,
+
+    /// Reuse the namespace of an earlier run instead of creating a new one
+    #[arg(long)]
+    run_id: Option<String>,
+
+    /// Delete everything written under the run's namespace once the run finishes
+    #[arg(long)]
+    cleanup: bool,
+
+    /// Write machine readable results to this file
+    #[arg(long)]
+    results: Option<String>,
+}
+
+/// Reports one phase of an iteration, adding its errors to the iteration's
+fn report(
+    label: &str,
+    mut outcome: PhaseOutcome,
+    errors: &ErrorArgs,
+    iteration_errors: &mut ErrorCounts,
+    num_requests: &mut u64,
+) -> IterationResult {
+    if errors.include_failed_latencies {
+        outcome.include_failed_latencies();
+    }
+    iteration_errors.merge(&outcome.errors);
+    *num_requests += outcome.num_requests();
+    outcome.report(label)
+}
+
+#[tokio::main]
+async fn main() {
+    object_store_bench::retries::init_logging();
+
+    let args = Args::parse();
+
+    let num_objects = args.num_objects.unwrap_or(10000);
+    let size_distribution = args
+        .size_distribution
+        .unwrap_or(SizeDistribution::Fixed(4096));
+    let max_concurrency = args.max_concurrency.unwrap_or(64);
+    let num_iterations = args.num_iterations.unwrap_or(1);
+    let mut rng = StdRng::seed_from_u64(args.seed.unwrap_or(0));
+
+    let (store, base_path) = args.store.build();
+    let timeline = args.timeline.start();
+    let store = timeline.wrap(store);
+    let namespace = RunNamespace::new(&base_path, args.run_id);
+    println!("Run id: {}", namespace.run_id);
+    println!(
+        "Writing {} objects with sizes {} and {} concurrent requests",
+        num_objects, size_distribution, max_concurrency
+    );
+
+    let sizes = (0..num_objects)
+        .map(|_| size_distribution.sample(&mut rng))
+        .collect::<Vec<_>>();
+    // Every object is a slice of the same random buffer so generating data doesn't dominate
+    let data = random_bytes(size_distribution.max());
+
+    let mut results = RunResults::new(
+        "small_objects",
+        &namespace.run_id,
+        namespace.prefix.as_ref(),
+    );
+    args.store.record(&mut results);
+
+    let mut stopped_early = false;
+    let mut schedule = args.timing.schedule(num_iterations);
+    while schedule.next_iteration() {
+        let mut iteration = Vec::new();
+        let mut errors = ErrorCounts::default();
+        let mut num_requests = 0;
+        let put = run_phase(num_objects, max_concurrency, |idx| {
+            let store = store.clone();
+            let path = namespace.path(&format!("small/{}", idx));
+            let size = sizes[idx as usize];
+            let payload = PutPayload::from_bytes(data.slice(0..size as usize));
+            async move { store.put(&path, payload).await.map(|_| size) }
+        })
+        .await;
+        iteration.push(report(
+            "put",
+            put,
+            &args.errors,
+            &mut errors,
+            &mut num_requests,
+        ));
+
+        let get = run_phase(num_objects, max_concurrency, |idx| {
+            let store = store.clone();
+            let path = namespace.path(&format!("small/{}", idx));
+            let size = sizes[idx as usize];
+            async move {
+                let bytes = store.get(&path).await?.bytes().await?;
+                if bytes.len() as u64 != size {
+                    return Err(object_store::Error::Generic {
+                        store: "small_objects",
+                        source: format!("expected {} bytes but read {}", size, bytes.len()).into(),
+                    });
+                }
+                Ok(size)
+            }
+        })
+        .await;
+        iteration.push(report(
+            "get",
+            get,
+            &args.errors,
+            &mut errors,
+            &mut num_requests,
+        ));
+        stopped_early = args.errors.should_stop(&errors, num_requests, &mut results);
+        schedule.record(iteration, &mut results);
+        if stopped_early {
+            break;
+        }
+    }
+    schedule.finish(&mut results);
+
+    timeline.finish(&mut results);
+    if let Some(results_path) = args.results {
+        results.write(results_path);
+    }
+
+    if args.cleanup {
+        let num_deleted = namespace.cleanup(store.as_ref()).await;
+        println!("Deleted {} objects under {}", num_deleted, namespace.prefix);
+    }
+
+    if stopped_early {
+        std::process::exit(1);
+    }
+}
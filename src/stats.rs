@@ -0,0 +1,86 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+/// Percentiles of a set of latency samples, in seconds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl LatencySummary {
+    /// Summarizes `samples`, sorting them in place.  Returns `None` if there are no samples.
+    pub fn from_samples(samples: &mut [f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_by(|a, b| a.total_cmp(b));
+        Some(Self {
+            count: samples.len(),
+            mean: samples.iter().sum::<f64>() / samples.len() as f64,
+            p50: percentile(samples, 50.0),
+            p90: percentile(samples, 90.0),
+            p99: percentile(samples, 99.0),
+            max: samples[samples.len() - 1],
+        })
+    }
+}
+
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "p50={:?} p90={:?} p99={:?} max={:?} seconds",
+            self.p50, self.p90, self.p99, self.max
+        )
+    }
+}
+
+/// Nearest-rank percentile of already sorted, non-empty `sorted`
+pub fn percentile(sorted: &[f64], pct: f64) -> f64 {
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Counts of latencies in power-of-two buckets, from 1us upwards
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    /// `(upper bound in seconds, count)` for each non-empty bucket
+    pub buckets: Vec<(f64, u64)>,
+}
+
+impl LatencyHistogram {
+    pub fn from_samples(samples: &[f64]) -> Self {
+        let mut counts = BTreeMap::<u32, u64>::new();
+        for sample in samples {
+            let micros = (sample * 1_000_000.0).max(1.0);
+            *counts.entry(micros.log2().ceil() as u32).or_default() += 1;
+        }
+        let buckets = counts
+            .into_iter()
+            .map(|(exp, count)| (2f64.powi(exp as i32) / 1_000_000.0, count))
+            .collect();
+        Self { buckets }
+    }
+}
+
+impl fmt::Display for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let max_count = self
+            .buckets
+            .iter()
+            .map(|(_, count)| *count)
+            .max()
+            .unwrap_or(1);
+        for (upper, count) in &self.buckets {
+            let bar = "#".repeat(((count * 50).div_ceil(max_count)) as usize);
+            writeln!(f, "  <= {:>12.6}s {:>10} {}", upper, count, bar)?;
+        }
+        Ok(())
+    }
+}
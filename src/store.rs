@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use object_store::{
+    aws::{AmazonS3Builder, AmazonS3ConfigKey},
+    gcp::GoogleCloudStorageBuilder,
+    local::LocalFileSystem,
+    memory::InMemory,
+    path::Path,
+    ObjectStore, RetryConfig,
+};
+
+use crate::{client::ClientArgs, results::RunResults};
+
+/// Options for the store a workload runs against
+#[derive(clap::Args, Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StoreArgs {
+    /// `s3://bucket[/prefix]`, `gs://bucket[/prefix]`, `memory` or a local directory
+    #[arg(long)]
+    pub base_uri: String,
+
+    #[arg(long)]
+    pub access_key: Option<String>,
+
+    #[arg(long)]
+    pub secret_key: Option<String>,
+
+    /// How S3 should implement conditional puts, `etag` or `dynamo:<table>`
+    #[arg(long)]
+    pub s3_conditional_put: Option<String>,
+
+    #[command(flatten)]
+    #[serde(default)]
+    pub client: ClientArgs,
+}
+
+impl StoreArgs {
+    /// Creates the store and returns it along with the prefix, within the store, that
+    /// `base_uri` points at
+    pub fn build(&self) -> (Arc<dyn ObjectStore>, Path) {
+        if let Some(rest) = self.base_uri.strip_prefix("s3://") {
+            let (bucket, prefix) = split_bucket(rest);
+            let mut store = AmazonS3Builder::new()
+                .with_bucket_name(bucket)
+                .with_region("us-east-1")
+                .with_retry(self.client.retry_config(RetryConfig::default()))
+                .with_client_options(self.client.client_options());
+            if let Some(access_key) = self.access_key.clone() {
+                store = store.with_access_key_id(access_key);
+            }
+            if let Some(secret_key) = self.secret_key.clone() {
+                store = store.with_secret_access_key(secret_key);
+            }
+            if let Some(conditional_put) = self.s3_conditional_put.clone() {
+                store = store.with_config(AmazonS3ConfigKey::ConditionalPut, conditional_put);
+            }
+            (Arc::new(store.build().unwrap()), prefix)
+        } else if let Some(rest) = self.base_uri.strip_prefix("gs://") {
+            let (bucket, prefix) = split_bucket(rest);
+            let store = GoogleCloudStorageBuilder::new()
+                .with_bucket_name(bucket)
+                .with_retry(self.client.retry_config(RetryConfig::default()))
+                .with_client_options(self.client.client_options())
+                .build()
+                .unwrap();
+            (Arc::new(store), prefix)
+        } else if self.base_uri == "memory" {
+            (Arc::new(InMemory::new()), Path::default())
+        } else {
+            let dir = self
+                .base_uri
+                .strip_prefix("file://")
+                .unwrap_or(&self.base_uri);
+            std::fs::create_dir_all(dir).unwrap();
+            (
+                Arc::new(LocalFileSystem::new_with_prefix(dir).unwrap()),
+                Path::default(),
+            )
+        }
+    }
+
+    /// A client of the same store as `store`, with its own connection pool.  An in-memory store
+    /// only exists once, so that one is shared.
+    pub fn another_client(&self, store: &Arc<dyn ObjectStore>) -> Arc<dyn ObjectStore> {
+        if self.base_uri == "memory" {
+            store.clone()
+        } else {
+            self.build().0
+        }
+    }
+
+    /// Records the retry and client options in the run's summary, if the store is remote
+    pub fn record(&self, results: &mut RunResults) {
+        if self.base_uri.starts_with("s3://") || self.base_uri.starts_with("gs://") {
+            self.client
+                .record(&self.client.retry_config(RetryConfig::default()), results);
+        }
+    }
+}
+
+fn split_bucket(bucket_and_prefix: &str) -> (&str, Path) {
+    match bucket_and_prefix.split_once('/') {
+        Some((bucket, prefix)) => (bucket, Path::from(prefix)),
+        None => (bucket_and_prefix, Path::default()),
+    }
+}
@@ -0,0 +1,350 @@
+use std::{process::Command, str::FromStr};
+
+use clap::Parser;
+use object_store_bench::results::{RunResults, RunTotals};
+
+/// Runs a workload for every combination of parameter values and tabulates the results
+///
+/// e.g. `sweep --param num-clients=1,2,4,8 --param download-size=8388608..67108864*2 --
+/// object_store_bench --bucket my-bucket --run-id <id> --skip-upload`
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// A parameter to sweep as `<flag name>=<values>`.  Values are a comma separated list of
+    /// numbers and ranges: `a..b` (step 1), `a..b+s` (add s) or `a..b*f` (multiply by f).
+    #[arg(long = "param", required = true)]
+    params: Vec<SweepParam>,
+
+    /// Also write the table to this CSV file
+    #[arg(long)]
+    csv: Option<String>,
+
+    /// Show the output of each run instead of just the table
+    #[arg(long)]
+    verbose: bool,
+
+    /// The workload command to run, which must accept `--results <file>`
+    #[arg(last = true, required = true)]
+    command: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct SweepParam {
+    name: String,
+    values: Vec<String>,
+}
+
+impl FromStr for SweepParam {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, spec) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected <name>=<values>, got '{}'", s))?;
+        let mut values = Vec::new();
+        for item in spec.split(',') {
+            match item.split_once("..") {
+                Some((start, rest)) => values.extend(expand_range(start, rest)?),
+                None => {
+                    item.parse::<f64>()
+                        .map_err(|_| format!("'{}' is not a number", item))?;
+                    values.push(item.to_string());
+                }
+            }
+        }
+        Ok(Self {
+            name: name.trim_start_matches("--").to_string(),
+            values,
+        })
+    }
+}
+
+fn expand_range(start: &str, rest: &str) -> Result<Vec<String>, String> {
+    let parse = |n: &str| {
+        n.parse::<u64>()
+            .map_err(|_| format!("'{}' is not a whole number", n))
+    };
+    let (end, step, multiply) = if let Some((end, f)) = rest.split_once('*') {
+        let factor = parse(f)?;
+        if factor < 2 {
+            return Err("range factor must be at least 2".to_string());
+        }
+        (end, factor, true)
+    } else if let Some((end, s)) = rest.split_once('+') {
+        (end, parse(s)?.max(1), false)
+    } else {
+        (rest, 1, false)
+    };
+    let (mut value, end) = (parse(start)?, parse(end)?);
+    if value == 0 && rest.contains('*') {
+        return Err("a multiplied range can't start at 0".to_string());
+    }
+    let mut values = Vec::new();
+    while value <= end {
+        values.push(value.to_string());
+        // Stop once the next value no longer fits in a u64
+        let next = if multiply {
+            value.checked_mul(step)
+        } else {
+            value.checked_add(step)
+        };
+        match next {
+            Some(next) => value = next,
+            None => break,
+        }
+    }
+    Ok(values)
+}
+
+/// Every combination of one value from each parameter
+fn combinations(params: &[SweepParam]) -> Vec<Vec<String>> {
+    params.iter().fold(vec![Vec::new()], |combos, param| {
+        combos
+            .iter()
+            .flat_map(|combo| {
+                param.values.iter().map(move |value| {
+                    let mut combo = combo.clone();
+                    combo.push(value.clone());
+                    combo
+                })
+            })
+            .collect()
+    })
+}
+
+fn run_one(
+    args: &Args,
+    combo: &[String],
+    results_path: &std::path::Path,
+) -> Result<RunTotals, String> {
+    let mut command = Command::new(&args.command[0]);
+    command.args(&args.command[1..]);
+    for (param, value) in args.params.iter().zip(combo) {
+        command.arg(format!("--{}", param.name)).arg(value);
+    }
+    command.arg("--results").arg(results_path);
+    if !args.verbose {
+        command.stdout(std::process::Stdio::null());
+    }
+    let status = command
+        .status()
+        .map_err(|e| format!("failed to run {}: {}", args.command[0], e))?;
+    if !status.success() {
+        return Err(format!("exited with {}", status));
+    }
+    let totals = RunResults::read(results_path)?.totals();
+    let _ = std::fs::remove_file(results_path);
+    Ok(totals)
+}
+
+/// Quotes a CSV cell if it needs it, doubling any quotes inside
+fn csv_cell(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+fn format_latency(latency: Option<f64>) -> String {
+    latency.map(|l| format!("{:.6}", l)).unwrap_or_default()
+}
+
+fn main() {
+    env_logger::init();
+
+    let args = Args::parse();
+
+    let combos = combinations(&args.params);
+    println!("Sweeping {} combinations", combos.len());
+
+    let mut header = args
+        .params
+        .iter()
+        .map(|p| p.name.clone())
+        .collect::<Vec<_>>();
+    header.extend(
+        [
+            "ops_per_sec",
+            "mib_per_sec",
+            "p50_secs",
+            "p99_secs",
+            "status",
+        ]
+        .map(String::from),
+    );
+    let mut rows = Vec::with_capacity(combos.len());
+    let mut best: Option<(usize, f64)> = None;
+    for (idx, combo) in combos.iter().enumerate() {
+        let results_path =
+            std::env::temp_dir().join(format!("sweep-{}-{}.json", std::process::id(), idx));
+        let mut row = combo.clone();
+        match run_one(&args, combo, &results_path) {
+            Ok(totals) => {
+                row.push(format!("{:.1}", totals.ops_per_sec()));
+                row.push(format!("{:.1}", totals.mib_per_sec()));
+                row.push(format_latency(totals.p50));
+                row.push(format_latency(totals.p99));
+                row.push("ok".to_string());
+                // Rank by bandwidth when the workload moves data, otherwise by request rate
+                let throughput = if totals.num_bytes > 0 {
+                    totals.mib_per_sec()
+                } else {
+                    totals.ops_per_sec()
+                };
+                if best.is_none_or(|(_, best)| throughput > best) {
+                    best = Some((idx, throughput));
+                }
+            }
+            Err(e) => {
+                log::error!("Run {:?} failed: {}", combo, e);
+                row.extend(["", "", "", ""].map(String::from));
+                row.push(e);
+            }
+        }
+        println!("{}", row.join("\t"));
+        rows.push(row);
+    }
+
+    let widths = header
+        .iter()
+        .enumerate()
+        .map(|(col, name)| {
+            rows.iter()
+                .map(|row| row[col].len())
+                .max()
+                .unwrap_or(0)
+                .max(name.len())
+        })
+        .collect::<Vec<_>>();
+    let format_row = |row: &[String]| {
+        row.iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:>width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+    println!();
+    println!("{}", format_row(&header));
+    for row in &rows {
+        println!("{}", format_row(row));
+    }
+    if let Some((idx, _)) = best {
+        let combo = args
+            .params
+            .iter()
+            .zip(&combos[idx])
+            .map(|(param, value)| format!("{}={}", param.name, value))
+            .collect::<Vec<_>>();
+        println!("Highest throughput: {}", combo.join(" "));
+    }
+
+    if let Some(csv) = &args.csv {
+        let lines = std::iter::once(&header)
+            .chain(&rows)
+            .map(|row| {
+                row.iter()
+                    .map(|cell| csv_cell(cell))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect::<Vec<_>>();
+        std::fs::write(csv, lines.join("\n") + "\n").unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        let cases = [
+            ("1", "4", Ok(vec!["1", "2", "3", "4"])),
+            ("4", "4", Ok(vec!["4"])),
+            ("5", "4", Ok(vec![])),
+            ("0", "10+5", Ok(vec!["0", "5", "10"])),
+            ("0", "9+5", Ok(vec!["0", "5"])),
+            ("3", "5+0", Ok(vec!["3", "4", "5"])),
+            ("1", "8*2", Ok(vec!["1", "2", "4", "8"])),
+            ("3", "100*10", Ok(vec!["3", "30"])),
+            // Stops instead of overflowing
+            (
+                "18446744073709551614",
+                "18446744073709551615",
+                Ok(vec!["18446744073709551614", "18446744073709551615"]),
+            ),
+            (
+                "18446744073709551610",
+                "18446744073709551615+4",
+                Ok(vec!["18446744073709551610", "18446744073709551614"]),
+            ),
+            (
+                "9223372036854775808",
+                "18446744073709551615*2",
+                Ok(vec!["9223372036854775808"]),
+            ),
+            ("1", "8*1", Err("range factor must be at least 2")),
+            ("0", "8*2", Err("a multiplied range can't start at 0")),
+            ("-1", "8", Err("'-1' is not a whole number")),
+            ("1.5", "8", Err("'1.5' is not a whole number")),
+            ("1", "", Err("'' is not a whole number")),
+            ("1", "8+x", Err("'x' is not a whole number")),
+        ];
+        for (start, rest, expected) in cases {
+            let expected = expected
+                .map(|values| values.into_iter().map(String::from).collect())
+                .map_err(String::from);
+            assert_eq!(expand_range(start, rest), expected, "{}..{}", start, rest);
+        }
+    }
+
+    #[test]
+    fn params() {
+        let param = "--num-clients=1,2..4,16..64*2"
+            .parse::<SweepParam>()
+            .unwrap();
+        assert_eq!(param.name, "num-clients");
+        assert_eq!(param.values, ["1", "2", "3", "4", "16", "32", "64"]);
+
+        let param = "fraction=0.5,1e3".parse::<SweepParam>().unwrap();
+        assert_eq!(param.values, ["0.5", "1e3"]);
+
+        assert!("num-clients".parse::<SweepParam>().is_err());
+        assert!("num-clients=1,x".parse::<SweepParam>().is_err());
+        assert!("num-clients=1..x".parse::<SweepParam>().is_err());
+    }
+
+    #[test]
+    fn every_combination() {
+        let param = |name: &str, values: &[&str]| SweepParam {
+            name: name.to_string(),
+            values: values.iter().map(|v| v.to_string()).collect(),
+        };
+        assert_eq!(combinations(&[]), vec![Vec::<String>::new()]);
+        assert_eq!(
+            combinations(&[param("a", &["1", "2"]), param("b", &["x", "y", "z"])]),
+            [
+                ["1", "x"],
+                ["1", "y"],
+                ["1", "z"],
+                ["2", "x"],
+                ["2", "y"],
+                ["2", "z"]
+            ]
+        );
+        assert!(combinations(&[param("a", &["1"]), param("b", &[])]).is_empty());
+    }
+
+    #[test]
+    fn csv_cells() {
+        assert_eq!(csv_cell("12.5"), "12.5");
+        assert_eq!(csv_cell(""), "");
+        assert_eq!(csv_cell("a,b"), "\"a,b\"");
+        assert_eq!(
+            csv_cell("exited with \"signal: 9\""),
+            "\"exited with \"\"signal: 9\"\"\""
+        );
+        assert_eq!(csv_cell("two\nlines"), "\"two\nlines\"");
+    }
+}
@@ -0,0 +1,482 @@
+use std::{
+    fmt,
+    future::Future,
+    ops::Range,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{stream::BoxStream, Stream, StreamExt, TryStreamExt};
+use object_store::{
+    path::Path, GetOptions, GetResult, GetResultPayload, ListResult, MultipartUpload, ObjectMeta,
+    ObjectStore, PutMultipartOpts, PutOptions, PutPayload, PutResult, Result, UploadPart,
+};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use crate::{
+    args::parse_period,
+    dashboard::Dashboard,
+    results::RunResults,
+    retries::{self, RetryTotals},
+    stats::LatencySummary,
+};
+
+/// Options for the per-interval timeline recorded in the results
+#[derive(clap::Args, Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimelineArgs {
+    /// Seconds between timeline samples, defaults to 1
+    #[arg(long, value_parser = parse_period)]
+    #[serde(deserialize_with = "crate::args::deserialize_period")]
+    pub timeline_interval: Option<f64>,
+
+    /// Print each timeline sample as it is taken
+    #[arg(long)]
+    pub live: bool,
+
+    /// Show a dashboard that redraws every interval instead, falling back to --live when
+    /// stdout isn't a terminal
+    #[arg(long)]
+    pub dashboard: bool,
+}
+
+/// What happened during one interval of the run.  `in_flight` is sampled at the end of the
+/// interval, everything else counts requests that finished during it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelinePoint {
+    /// Seconds since the timeline started, at the end of the interval
+    pub t_secs: f64,
+    pub ops: u64,
+    pub bytes: u64,
+    pub errors: u64,
+    pub in_flight: u64,
+    /// Retries the store's client made during the interval, and how many of those were throttled
+    #[serde(default)]
+    pub retries: u64,
+    #[serde(default)]
+    pub throttled: u64,
+}
+
+impl fmt::Display for TimelinePoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{:>8.1}s] {} ops {} bytes {} errors {} in flight",
+            self.t_secs, self.ops, self.bytes, self.errors, self.in_flight
+        )?;
+        if self.retries > 0 {
+            write!(
+                f,
+                " {} retries ({} throttled)",
+                self.retries, self.throttled
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    ops: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+    in_flight: AtomicU64,
+    /// Latencies of the requests in the current interval, only kept for the dashboard
+    latencies: Option<Mutex<Vec<f64>>>,
+    /// Bytes the whole run should move, 0 if unknown
+    expected_bytes: AtomicU64,
+}
+
+impl Counters {
+    fn record<T>(&self, result: &Result<T>, num_bytes: impl FnOnce(&T) -> u64) {
+        match result {
+            Ok(value) => {
+                self.ops.fetch_add(1, Ordering::Relaxed);
+                self.bytes.fetch_add(num_bytes(value), Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Counts `fut` as in flight until it finishes (or is dropped) and then records its result
+    async fn track<T>(
+        self: &Arc<Self>,
+        fut: impl Future<Output = Result<T>>,
+        num_bytes: impl FnOnce(&T) -> u64,
+    ) -> Result<T> {
+        let _in_flight = InFlight::new(self.clone());
+        let start = Instant::now();
+        let result = retries::attribute(fut).await;
+        if let Some(latencies) = &self.latencies {
+            latencies
+                .lock()
+                .unwrap()
+                .push(start.elapsed().as_secs_f64());
+        }
+        self.record(&result, num_bytes);
+        result
+    }
+}
+
+struct InFlight(Arc<Counters>);
+
+impl InFlight {
+    fn new(counters: Arc<Counters>) -> Self {
+        counters.in_flight.fetch_add(1, Ordering::Relaxed);
+        Self(counters)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Samples the requests made through the stores it wraps at a fixed interval
+pub struct Timeline {
+    counters: Arc<Counters>,
+    points: Arc<Mutex<Vec<TimelinePoint>>>,
+    start: Instant,
+    /// Retry totals as of the last sample, locked while a sample is taken
+    last_retries: Arc<Mutex<RetryTotals>>,
+    sampler: JoinHandle<()>,
+}
+
+impl TimelineArgs {
+    /// Starts sampling, must be called within a tokio runtime
+    pub fn start(&self) -> Timeline {
+        let interval = Duration::from_secs_f64(self.timeline_interval.unwrap_or(1.0));
+        let mut dashboard = self
+            .dashboard
+            .then(|| Dashboard::new(interval.as_secs_f64()))
+            .flatten();
+        let live = self.live || (self.dashboard && dashboard.is_none());
+        let counters = Arc::new(Counters {
+            latencies: dashboard.is_some().then(Mutex::default),
+            ..Default::default()
+        });
+        let points = Arc::new(Mutex::new(Vec::new()));
+        let start = Instant::now();
+        let last_retries = Arc::new(Mutex::new(RetryTotals::now()));
+        let sampler = tokio::spawn({
+            let counters = counters.clone();
+            let points = points.clone();
+            let last_retries = last_retries.clone();
+            async move {
+                let mut ticker = tokio::time::interval(interval);
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    let mut last_retries = last_retries.lock().unwrap();
+                    let point = sample(&counters, start, &mut last_retries);
+                    if let Some(dashboard) = &mut dashboard {
+                        let mut latencies = counters
+                            .latencies
+                            .as_ref()
+                            .map(|l| std::mem::take(&mut *l.lock().unwrap()))
+                            .unwrap_or_default();
+                        let expected_bytes = counters.expected_bytes.load(Ordering::Relaxed);
+                        dashboard.update(
+                            &point,
+                            LatencySummary::from_samples(&mut latencies).as_ref(),
+                            (expected_bytes > 0).then_some(expected_bytes),
+                        );
+                    } else if live {
+                        println!("{}", point);
+                    }
+                    points.lock().unwrap().push(point);
+                }
+            }
+        });
+        Timeline {
+            counters,
+            points,
+            start,
+            last_retries,
+            sampler,
+        }
+    }
+}
+
+/// Takes the counts since the last sample
+fn sample(counters: &Counters, start: Instant, last_retries: &mut RetryTotals) -> TimelinePoint {
+    let retries = RetryTotals::now();
+    let point = TimelinePoint {
+        t_secs: start.elapsed().as_secs_f64(),
+        ops: counters.ops.swap(0, Ordering::Relaxed),
+        bytes: counters.bytes.swap(0, Ordering::Relaxed),
+        errors: counters.errors.swap(0, Ordering::Relaxed),
+        in_flight: counters.in_flight.load(Ordering::Relaxed),
+        retries: retries.retries - last_retries.retries,
+        throttled: retries.throttled_retries - last_retries.throttled_retries,
+    };
+    *last_retries = retries;
+    point
+}
+
+impl Timeline {
+    /// Wraps `store` so that requests made through it show up in the timeline
+    pub fn wrap(&self, store: Arc<dyn ObjectStore>) -> Arc<dyn ObjectStore> {
+        Arc::new(TimelineStore {
+            inner: store,
+            counters: self.counters.clone(),
+        })
+    }
+
+    /// Lets the dashboard show progress towards moving `bytes` in total
+    pub fn expect_bytes(&self, bytes: u64) {
+        self.counters.expected_bytes.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Stops sampling and adds the timeline to `results`, along with the retries made over the
+    /// whole run.  Requests since the last sample end up in a final, shorter interval.
+    pub fn finish(self, results: &mut RunResults) {
+        self.sampler.abort();
+        // Waits for a sample the sampler may be taking right now, it won't take another
+        let point = sample(
+            &self.counters,
+            self.start,
+            &mut self.last_retries.lock().unwrap(),
+        );
+        let mut points = std::mem::take(&mut *self.points.lock().unwrap());
+        points.push(point);
+        results.timeline = points;
+        RetryTotals::now().report(results);
+    }
+}
+
+/// An [`ObjectStore`] that counts the requests made through it
+#[derive(Debug)]
+struct TimelineStore {
+    inner: Arc<dyn ObjectStore>,
+    counters: Arc<Counters>,
+}
+
+impl fmt::Display for TimelineStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Timeline({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for TimelineStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        let num_bytes = payload.content_length() as u64;
+        self.counters
+            .track(self.inner.put_opts(location, payload, opts), |_| num_bytes)
+            .await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        let upload = self
+            .counters
+            .track(self.inner.put_multipart_opts(location, opts), |_| 0)
+            .await?;
+        Ok(Box::new(TimelineUpload {
+            inner: upload,
+            counters: self.counters.clone(),
+        }))
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let head = options.head;
+        let mut result = self
+            .counters
+            .track(self.inner.get_opts(location, options), |_| 0)
+            .await?;
+        // Count the body as it arrives so long downloads are spread over the intervals
+        result.payload = match result.payload {
+            GetResultPayload::Stream(stream) => {
+                let counters = self.counters.clone();
+                GetResultPayload::Stream(
+                    stream
+                        .inspect_ok(move |bytes| {
+                            counters
+                                .bytes
+                                .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+                        })
+                        .boxed(),
+                )
+            }
+            GetResultPayload::File(file, path) => {
+                if !head {
+                    self.counters
+                        .bytes
+                        .fetch_add(result.range.len() as u64, Ordering::Relaxed);
+                }
+                GetResultPayload::File(file, path)
+            }
+        };
+        Ok(result)
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        self.counters
+            .track(self.inner.get_range(location, range), |bytes| {
+                bytes.len() as u64
+            })
+            .await
+    }
+
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        self.counters
+            .track(self.inner.get_ranges(location, ranges), |parts| {
+                parts.iter().map(|bytes| bytes.len() as u64).sum()
+            })
+            .await
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        self.counters.track(self.inner.head(location), |_| 0).await
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.counters
+            .track(self.inner.delete(location), |_| 0)
+            .await
+    }
+
+    fn delete_stream<'a>(
+        &'a self,
+        locations: BoxStream<'a, Result<Path>>,
+    ) -> BoxStream<'a, Result<Path>> {
+        // Stores batch these, so count each deleted object rather than each request
+        Tracked::new(self.inner.delete_stream(locations), &self.counters, true).boxed()
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        Tracked::new(self.inner.list(prefix), &self.counters, false).boxed()
+    }
+
+    fn list_with_offset(
+        &self,
+        prefix: Option<&Path>,
+        offset: &Path,
+    ) -> BoxStream<'_, Result<ObjectMeta>> {
+        Tracked::new(
+            self.inner.list_with_offset(prefix, offset),
+            &self.counters,
+            false,
+        )
+        .boxed()
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.counters
+            .track(self.inner.list_with_delimiter(prefix), |_| 0)
+            .await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.counters.track(self.inner.copy(from, to), |_| 0).await
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.counters
+            .track(self.inner.rename(from, to), |_| 0)
+            .await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.counters
+            .track(self.inner.copy_if_not_exists(from, to), |_| 0)
+            .await
+    }
+
+    async fn rename_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.counters
+            .track(self.inner.rename_if_not_exists(from, to), |_| 0)
+            .await
+    }
+}
+
+/// Counts a streaming request as in flight until the stream ends, and as one op when it does
+/// (or one op per item, if `op_per_item`)
+struct Tracked<S> {
+    inner: S,
+    counters: Arc<Counters>,
+    in_flight: Option<InFlight>,
+    op_per_item: bool,
+}
+
+impl<S> Tracked<S> {
+    fn new(inner: S, counters: &Arc<Counters>, op_per_item: bool) -> Self {
+        Self {
+            inner,
+            counters: counters.clone(),
+            in_flight: Some(InFlight::new(counters.clone())),
+            op_per_item,
+        }
+    }
+}
+
+impl<S, T> Stream for Tracked<S>
+where
+    S: Stream<Item = Result<T>> + Unpin,
+{
+    type Item = Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = futures::ready!(self.inner.poll_next_unpin(cx));
+        match &item {
+            Some(Err(_)) => {
+                self.counters.errors.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(Ok(_)) if self.op_per_item => {
+                self.counters.ops.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(Ok(_)) => {}
+            None => {
+                if self.in_flight.take().is_some() && !self.op_per_item {
+                    self.counters.ops.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+/// A [`MultipartUpload`] that counts each part as it finishes uploading
+#[derive(Debug)]
+struct TimelineUpload {
+    inner: Box<dyn MultipartUpload>,
+    counters: Arc<Counters>,
+}
+
+#[async_trait]
+impl MultipartUpload for TimelineUpload {
+    fn put_part(&mut self, data: PutPayload) -> UploadPart {
+        let num_bytes = data.content_length() as u64;
+        let part = self.inner.put_part(data);
+        let counters = self.counters.clone();
+        Box::pin(async move { counters.track(part, |_| num_bytes).await })
+    }
+
+    async fn complete(&mut self) -> Result<PutResult> {
+        self.counters.track(self.inner.complete(), |_| 0).await
+    }
+
+    async fn abort(&mut self) -> Result<()> {
+        self.counters.track(self.inner.abort(), |_| 0).await
+    }
+}
@@ -0,0 +1,195 @@
+use std::time::Instant;
+
+use serde::Deserialize;
+
+use crate::{
+    results::{IterationResult, RunResults},
+    retries::RetryTotals,
+};
+
+/// Options for how long a workload runs
+#[derive(clap::Args, Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimingArgs {
+    /// Keep running iterations until this many seconds have passed instead of running a fixed
+    /// number of them
+    #[arg(long)]
+    pub duration: Option<f64>,
+
+    /// Run iterations for this many seconds first, to warm up connections, and discard their
+    /// results
+    #[arg(long)]
+    pub warmup: Option<f64>,
+
+    /// Print totals for the iterations that finished in each period of this many seconds
+    #[arg(long)]
+    pub report_interval: Option<f64>,
+}
+
+impl TimingArgs {
+    pub fn schedule(&self, num_iterations: u32) -> Schedule {
+        Schedule {
+            args: self.clone(),
+            num_iterations,
+            start: None,
+            measured_start: None,
+            warming_up: false,
+            num_warmup: 0,
+            num_measured: 0,
+            iteration_retries: RetryTotals::default(),
+            interval: Interval::new(),
+        }
+    }
+}
+
+/// Decides which iterations to run and keeps the results of those after the warmup.  Call
+/// [`Schedule::record`] after each iteration that [`Schedule::next_iteration`] allowed, then
+/// [`Schedule::finish`].
+#[derive(Debug)]
+pub struct Schedule {
+    args: TimingArgs,
+    num_iterations: u32,
+    start: Option<Instant>,
+    measured_start: Option<Instant>,
+    warming_up: bool,
+    num_warmup: u32,
+    num_measured: u32,
+    /// Retries so far when the current iteration started
+    iteration_retries: RetryTotals,
+    interval: Interval,
+}
+
+impl Schedule {
+    /// Whether to run another iteration
+    pub fn next_iteration(&mut self) -> bool {
+        self.iteration_retries = RetryTotals::now();
+        let start = *self.start.get_or_insert_with(Instant::now);
+        if self
+            .args
+            .warmup
+            .is_some_and(|warmup| start.elapsed().as_secs_f64() < warmup)
+        {
+            if !self.warming_up {
+                println!("Warming up for {:?} seconds", self.args.warmup.unwrap());
+            }
+            self.warming_up = true;
+            return true;
+        }
+        if self.warming_up {
+            println!(
+                "Warmup done after {} iterations, measuring from now on",
+                self.num_warmup
+            );
+            self.warming_up = false;
+        }
+        let measured_start = *self.measured_start.get_or_insert_with(|| {
+            self.interval = Interval::new();
+            Instant::now()
+        });
+        match self.args.duration {
+            Some(duration) => measured_start.elapsed().as_secs_f64() < duration,
+            None => self.num_measured < self.num_iterations,
+        }
+    }
+
+    /// Adds the results of the iteration just run, unless it was part of the warmup
+    pub fn record(&mut self, mut iteration: Vec<IterationResult>, results: &mut RunResults) {
+        if self.warming_up {
+            self.num_warmup += 1;
+            return;
+        }
+        self.num_measured += 1;
+        let retries = RetryTotals::now().since(&self.iteration_retries);
+        for result in &mut iteration {
+            result.retries = Some(retries);
+        }
+        self.interval.add(&iteration, &retries);
+        if self
+            .args
+            .report_interval
+            .is_some_and(|period| self.interval.start.elapsed().as_secs_f64() >= period)
+        {
+            self.interval.report();
+            self.interval = Interval::new();
+        }
+        results.iterations.extend(iteration);
+    }
+
+    /// Reports the last, partial interval and notes the warmup in the results
+    pub fn finish(&mut self, results: &mut RunResults) {
+        if self.args.report_interval.is_some() && self.interval.num_iterations > 0 {
+            self.interval.report();
+        }
+        if self.num_warmup > 0 {
+            results
+                .summary
+                .insert("warmup_iterations".to_string(), self.num_warmup.into());
+        }
+        if let Some(measured_start) = self.measured_start {
+            results.summary.insert(
+                "measured_secs".to_string(),
+                measured_start.elapsed().as_secs_f64().into(),
+            );
+        }
+    }
+}
+
+/// Totals of the iterations that finished since the last interval report
+#[derive(Debug)]
+struct Interval {
+    start: Instant,
+    num_iterations: u32,
+    num_requests: u64,
+    num_bytes: u64,
+    num_errors: u64,
+    num_retries: u64,
+    num_throttled: u64,
+    worst_p99: Option<f64>,
+}
+
+impl Interval {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            num_iterations: 0,
+            num_requests: 0,
+            num_bytes: 0,
+            num_errors: 0,
+            num_retries: 0,
+            num_throttled: 0,
+            worst_p99: None,
+        }
+    }
+
+    fn add(&mut self, iteration: &[IterationResult], retries: &RetryTotals) {
+        self.num_iterations += 1;
+        self.num_retries += retries.retries;
+        self.num_throttled += retries.throttled_retries;
+        for result in iteration {
+            self.num_requests += result.num_requests;
+            self.num_bytes += result.num_bytes;
+            self.num_errors += result.errors.values().sum::<u64>();
+            if let Some(latency) = &result.latency {
+                self.worst_p99 = Some(self.worst_p99.unwrap_or(0.0).max(latency.p99));
+            }
+        }
+    }
+
+    fn report(&self) {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        println!(
+            "Interval of {:?} seconds: {} iterations ({} ops/s {} MiB/s{} {} errors, {} retries of \
+             which {} throttled)",
+            elapsed,
+            self.num_iterations,
+            self.num_requests as f64 / elapsed,
+            self.num_bytes as f64 / elapsed / (1024.0 * 1024.0),
+            self.worst_p99
+                .map(|p99| format!(" worst p99={:?} seconds", p99))
+                .unwrap_or_default(),
+            self.num_errors,
+            self.num_retries,
+            self.num_throttled,
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use crate::results::RunResults;
+
+/// Throughput and tail latency measured at one concurrency level
+#[derive(Debug, Clone)]
+pub struct RampStep {
+    pub concurrency: usize,
+    /// In whatever unit the workload cares about (ops/s, MiB/s, ...)
+    pub throughput: f64,
+    pub p99: f64,
+}
+
+/// Ramps concurrency geometrically until throughput stops increasing or tail latency blows up,
+/// then picks the knee: the lowest concurrency that gets (nearly) the peak throughput.
+#[derive(Debug)]
+pub struct ConcurrencyRamp {
+    steps: Vec<RampStep>,
+    next: Option<usize>,
+    max_concurrency: usize,
+    /// Each step multiplies the concurrency by this
+    pub factor: usize,
+    /// Throughput within this fraction of the peak counts as "not increasing"
+    pub min_gain: f64,
+    /// Stop once p99 exceeds this multiple of the p99 at the best step so far
+    pub max_latency_growth: f64,
+    /// Stop after this many steps in a row without a gain
+    pub patience: usize,
+    stalled_steps: usize,
+}
+
+impl ConcurrencyRamp {
+    pub fn new(start: usize, max_concurrency: usize) -> Self {
+        Self {
+            steps: Vec::new(),
+            next: Some(start.max(1)),
+            max_concurrency,
+            factor: 2,
+            min_gain: 0.05,
+            max_latency_growth: 4.0,
+            patience: 2,
+            stalled_steps: 0,
+        }
+    }
+
+    /// The concurrency to measure next, or `None` once the ramp is over
+    pub fn next_concurrency(&self) -> Option<usize> {
+        self.next
+    }
+
+    pub fn steps(&self) -> &[RampStep] {
+        &self.steps
+    }
+
+    pub fn record(&mut self, step: RampStep) {
+        let best = self.best().cloned();
+        let concurrency = step.concurrency;
+        self.steps.push(step.clone());
+
+        let stop = match best {
+            None => false,
+            Some(best) => {
+                if step.throughput > best.throughput * (1.0 + self.min_gain) {
+                    self.stalled_steps = 0;
+                } else {
+                    self.stalled_steps += 1;
+                }
+                self.stalled_steps >= self.patience || step.p99 > best.p99 * self.max_latency_growth
+            }
+        };
+        self.next = if stop || concurrency >= self.max_concurrency {
+            None
+        } else {
+            Some((concurrency * self.factor).min(self.max_concurrency))
+        };
+    }
+
+    fn best(&self) -> Option<&RampStep> {
+        self.steps
+            .iter()
+            .max_by(|a, b| a.throughput.total_cmp(&b.throughput))
+    }
+
+    /// The lowest concurrency whose throughput is within `min_gain` of the peak
+    pub fn knee(&self) -> Option<&RampStep> {
+        let peak = self.best()?.throughput;
+        self.steps
+            .iter()
+            .filter(|step| step.throughput >= peak * (1.0 - self.min_gain))
+            .min_by_key(|step| step.concurrency)
+    }
+
+    /// Prints the knee and records it in the run's summary
+    pub fn report(&self, unit: &str, results: &mut RunResults) {
+        let Some(knee) = self.knee() else {
+            println!("Knee: no steps were measured");
+            return;
+        };
+        println!(
+            "Knee: concurrency {} ({} {} p99={:?} seconds)",
+            knee.concurrency, knee.throughput, unit, knee.p99
+        );
+        if self.next.is_none() && self.steps.last().map(|s| s.concurrency) == Some(knee.concurrency)
+        {
+            println!("Throughput was still increasing at the maximum concurrency tried");
+        }
+        results
+            .summary
+            .insert("knee_concurrency".to_string(), knee.concurrency.into());
+        results
+            .summary
+            .insert("knee_throughput".to_string(), knee.throughput.into());
+        results
+            .summary
+            .insert("knee_p99_secs".to_string(), knee.p99.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Measures `(throughput, p99)` at each concurrency the ramp asks for, returning the
+    /// concurrencies it asked for
+    fn run(ramp: &mut ConcurrencyRamp, measurements: &[(f64, f64)]) -> Vec<usize> {
+        let mut tried = Vec::new();
+        for &(throughput, p99) in measurements {
+            let Some(concurrency) = ramp.next_concurrency() else {
+                break;
+            };
+            tried.push(concurrency);
+            ramp.record(RampStep {
+                concurrency,
+                throughput,
+                p99,
+            });
+        }
+        tried
+    }
+
+    #[test]
+    fn stops_after_patience_steps_without_a_gain() {
+        let mut ramp = ConcurrencyRamp::new(1, 1024);
+        let tried = run(
+            &mut ramp,
+            &[
+                (100.0, 0.01),
+                (200.0, 0.01),
+                (400.0, 0.01),
+                (410.0, 0.01),
+                (405.0, 0.01),
+                (1000.0, 0.01),
+            ],
+        );
+        assert_eq!(tried, vec![1, 2, 4, 8, 16]);
+        assert_eq!(ramp.next_concurrency(), None);
+        // 400 is within 5% of the 410 peak
+        assert_eq!(ramp.knee().unwrap().concurrency, 4);
+    }
+
+    #[test]
+    fn a_gain_resets_patience() {
+        let mut ramp = ConcurrencyRamp::new(1, 1024);
+        let tried = run(
+            &mut ramp,
+            &[
+                (100.0, 0.01),
+                (101.0, 0.01),
+                (200.0, 0.01),
+                (201.0, 0.01),
+                (300.0, 0.01),
+            ],
+        );
+        assert_eq!(tried, vec![1, 2, 4, 8, 16]);
+        assert_eq!(ramp.next_concurrency(), Some(32));
+        assert_eq!(ramp.knee().unwrap().concurrency, 16);
+    }
+
+    #[test]
+    fn stops_when_p99_grows_past_the_best_steps() {
+        let mut ramp = ConcurrencyRamp::new(1, 1024);
+        let tried = run(
+            &mut ramp,
+            &[(100.0, 0.01), (200.0, 0.01), (300.0, 0.05), (400.0, 0.01)],
+        );
+        assert_eq!(tried, vec![1, 2, 4]);
+        assert_eq!(ramp.next_concurrency(), None);
+        assert_eq!(ramp.knee().unwrap().concurrency, 4);
+    }
+
+    #[test]
+    fn stops_at_the_max_concurrency() {
+        let mut ramp = ConcurrencyRamp::new(3, 10);
+        let tried = run(
+            &mut ramp,
+            &[(100.0, 0.01), (200.0, 0.01), (300.0, 0.01), (400.0, 0.01)],
+        );
+        assert_eq!(tried, vec![3, 6, 10]);
+        assert_eq!(ramp.next_concurrency(), None);
+        assert_eq!(ramp.knee().unwrap().concurrency, 10);
+    }
+
+    #[test]
+    fn knee_is_the_lowest_concurrency_near_the_peak() {
+        let mut ramp = ConcurrencyRamp::new(0, 1024);
+        assert!(ramp.knee().is_none());
+        let tried = run(
+            &mut ramp,
+            &[(100.0, 0.01), (500.0, 0.01), (480.0, 0.01), (520.0, 0.02)],
+        );
+        // A zero start still measures one request in flight
+        assert_eq!(tried, vec![1, 2, 4, 8]);
+        assert_eq!(ramp.steps().len(), 4);
+        // Everything from 480 up is within 5% of the 520 peak
+        assert_eq!(ramp.knee().unwrap().concurrency, 2);
+    }
+}
@@ -1,125 +1,190 @@
-use std::{
-    sync::{Arc, Mutex},
-    time::Duration,
-};
+use std::{sync::Arc, time::Duration};
 
 use clap::Parser;
-use futures::StreamExt;
-use object_store::{
-    gcp::GoogleCloudStorageBuilder, path::Path, BackoffConfig, ObjectStore, PutPayload,
+use object_store::{gcp::GoogleCloudStorageBuilder, path::Path, BackoffConfig, RetryConfig};
+use object_store_bench::{
+    client::ClientArgs,
+    large_upload::{UploadArgs, UploadMode},
+    limiter::LimiterArgs,
+    memory::peak_rss_bytes,
+    namespace::RunNamespace,
+    results::{IterationResult, RunResults},
+    retries::RetryTotals,
+    timeline::TimelineArgs,
+    timing::TimingArgs,
 };
-use rand::{thread_rng, RngCore};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
-    #[arg(short, long)]
-    initial_part_size: Option<u64>,
-
-    #[arg(short, long)]
-    total_size: Option<u64>,
-
-    #[arg(short, long)]
-    max_parallelism: Option<u64>,
+    #[command(flatten)]
+    upload: UploadArgs,
 
     #[arg(short, long)]
     bucket: String,
 
+    /// Each upload writes a new object under this path
     #[arg(short, long)]
     path: Option<String>,
+
+    /// How many objects to upload one after the other, defaults to 1
+    #[arg(long)]
+    num_iterations: Option<u32>,
+
+    #[command(flatten)]
+    timing: TimingArgs,
+
+    /// Reuse the namespace of an earlier run instead of creating a new one
+    #[arg(long)]
+    run_id: Option<String>,
+
+    /// Delete everything written under the run's namespace once the run finishes
+    #[arg(long)]
+    cleanup: bool,
+
+    /// Write machine readable results to this file
+    #[arg(long)]
+    results: Option<String>,
+
+    #[command(flatten)]
+    timeline: TimelineArgs,
+
+    // Retry options not given default to `default_retry_config` rather than object_store's
+    // defaults
+    #[command(flatten)]
+    client: ClientArgs,
+
+    // Only applies to `--mode put-part`
+    #[command(flatten)]
+    limiter: LimiterArgs,
 }
 
-const PART_SIZE_INCREMENT: u64 = 5 * 1024 * 1024;
+/// Keep retrying for a long time, large uploads shouldn't fail because of throttling
+fn default_retry_config() -> RetryConfig {
+    RetryConfig {
+        max_retries: 1000,
+        retry_timeout: Duration::from_secs(10000),
+        backoff: BackoffConfig {
+            init_backoff: Duration::from_secs(5),
+            max_backoff: Duration::from_secs(30),
+            base: 2.,
+        },
+    }
+}
 
 #[tokio::main]
 async fn main() {
-    env_logger::init();
+    object_store_bench::retries::init_logging();
 
     let args = Args::parse();
 
+    let namespace = RunNamespace::new(&Path::default(), args.run_id);
+    log::info!("Run id: {}", namespace.run_id);
+
     let path = args.path.unwrap_or("big_upload.data".to_string());
-    let path = Path::from(path.as_str());
-
-    let total_size = args.total_size.unwrap_or(2 * 1024 * 1024 * 1024 * 1024);
-    let initial_part_size = args.initial_part_size.unwrap_or(PART_SIZE_INCREMENT);
-    let max_parallelism = args.max_parallelism.unwrap_or(32);
-
-    let make_store = move || {
-        let builder = GoogleCloudStorageBuilder::new()
-            .with_bucket_name(args.bucket.clone())
-            .with_retry(object_store::RetryConfig {
-                max_retries: 1000,
-                retry_timeout: Duration::from_secs(10000),
-                backoff: BackoffConfig {
-                    init_backoff: Duration::from_secs(5),
-                    max_backoff: Duration::from_secs(30),
-                    base: 2.,
-                },
-            });
-        Arc::new(builder.build().unwrap())
+    let path = namespace.path(&path);
+
+    let total_size = args.upload.total_size();
+    let num_iterations = args.num_iterations.unwrap_or(1);
+    let mut limiter = args.limiter.limiter(args.upload.max_parallelism() as usize);
+    if limiter.is_some() && !matches!(args.upload.mode, UploadMode::PutPart) {
+        log::error!("--scheduler only applies to --mode put-part");
+        std::process::exit(1);
+    }
+
+    let timeline = args.timeline.start();
+    let retry = args.client.retry_config(default_retry_config());
+    let client_options = args.client.client_options();
+    let make_store = {
+        let timeline = &timeline;
+        let retry = &retry;
+        let client_options = &client_options;
+        move || {
+            let builder = GoogleCloudStorageBuilder::new()
+                .with_bucket_name(args.bucket.clone())
+                .with_retry(retry.clone())
+                .with_client_options(client_options.clone());
+            timeline.wrap(Arc::new(builder.build().unwrap()))
+        }
     };
 
     let store = make_store();
-
-    let mut bytes_written = 0;
-    log::info!(
-        "Uploading {} bytes of data starting with chunks of size {}",
-        total_size,
-        initial_part_size
-    );
-
-    // Just initialize whatever garbage.  Max that `part_size` can reach is 99 * initial_part_size
-    // let max_part_size = 99 * initial_part_size as usize;
-    // let mut data = bytes::BytesMut::with_capacity(max_part_size);
-    // unsafe { data.set_len(max_part_size) };
-    // let data = data.freeze();
-
-    let multipart = Arc::new(Mutex::new(store.put_multipart(&path).await.unwrap()));
-    let total_start = std::time::Instant::now();
-
-    let mut tasks = Vec::with_capacity(10000);
-    while bytes_written < total_size {
-        // let data = data.clone();
-        let multipart = multipart.clone();
-        let part_size =
-            initial_part_size.max(((tasks.len() as u64 / 100) + 1) * PART_SIZE_INCREMENT);
-        tasks.push(async move {
-            let start = std::time::Instant::now();
-            let mut part = bytes::BytesMut::zeroed(part_size as usize);
-            thread_rng().fill_bytes(&mut part);
-            log::info!("About to upload {} bytes of data", part.len());
-            {
-                let mut multipart = multipart.lock().unwrap();
-                multipart.put_part(PutPayload::from_bytes(part.freeze()))
-            }
-            .await
-            .unwrap();
-            log::info!(
-                "Upload took {:?} seconds progress={}",
-                start.elapsed().as_secs_f64(),
-                bytes_written as f64 / total_size as f64
-            );
-        });
-        bytes_written += part_size;
+    // How much the dashboard should expect to move, only known for a fixed number of iterations
+    if args.timing.duration.is_none() {
+        timeline.expect_bytes(total_size * num_iterations as u64);
     }
-    log::info!("Generated {} tasks to upload", tasks.len());
-    futures::stream::iter(tasks)
-        .buffered(max_parallelism as usize)
-        .collect::<Vec<_>>()
-        .await;
-
-    let mut multipart = multipart.lock().unwrap();
-    loop {
-        match multipart.complete().await {
-            Ok(_) => break,
+
+    let mut results = RunResults::new("upload", &namespace.run_id, namespace.prefix.as_ref());
+    let mut parts = serde_json::Map::new();
+    let mut schedule = args.timing.schedule(num_iterations);
+    let mut iteration_idx = 0;
+    while schedule.next_iteration() {
+        let path = path.child(iteration_idx.to_string());
+        iteration_idx += 1;
+        log::info!(
+            "Uploading {} bytes of data to {} with {:?} starting with chunks of size {}",
+            total_size,
+            path,
+            args.upload.mode,
+            args.upload.initial_part_size()
+        );
+        let retries_before = RetryTotals::now();
+        let total_start = std::time::Instant::now();
+        let result = args
+            .upload
+            .upload(store.clone(), &path, limiter.as_mut())
+            .await;
+        let stats = match result {
+            Ok(stats) => stats,
             Err(e) => {
-                log::error!("Error completing multipart upload: {:?}", e);
-                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                log::error!("Upload failed and was aborted: {}", e);
+                std::process::exit(1);
             }
-        }
+        };
+        let total_elapsed = total_start.elapsed();
+        let retries = RetryTotals::now().since(&retries_before);
+        log::info!(
+            "Total upload took {:?} seconds ({} GiB/s, {} parts of {} to {} bytes, peak RSS {:?} \
+             bytes, {} retries of which {} throttled, {:?} seconds backing off)",
+            total_elapsed.as_secs_f64(),
+            stats.bytes_written as f64 / total_elapsed.as_secs_f64() / (1024.0 * 1024.0 * 1024.0),
+            stats.num_parts,
+            stats.min_part_size,
+            stats.max_part_size,
+            peak_rss_bytes(),
+            retries.retries,
+            retries.throttled_retries,
+            retries.backoff_secs
+        );
+
+        // Every upload uses the same part sizes
+        parts.insert("num_parts".to_string(), stats.num_parts.into());
+        parts.insert("min_part_size".to_string(), stats.min_part_size.into());
+        parts.insert("max_part_size".to_string(), stats.max_part_size.into());
+        let iteration = IterationResult {
+            label: args.upload.mode_name(),
+            elapsed_secs: total_elapsed.as_secs_f64(),
+            num_requests: stats.num_parts,
+            num_bytes: stats.bytes_written,
+            ..Default::default()
+        };
+        schedule.record(vec![iteration], &mut results);
+    }
+    schedule.finish(&mut results);
+
+    results.peak_rss_bytes = peak_rss_bytes();
+    args.client.record(&retry, &mut results);
+    if let Some(limiter) = &limiter {
+        limiter.report(&mut results);
+    }
+    results.summary.insert("parts".to_string(), parts.into());
+    timeline.finish(&mut results);
+    if let Some(results_path) = args.results {
+        results.write(results_path);
+    }
+
+    if args.cleanup {
+        let num_deleted = namespace.cleanup(store.as_ref()).await;
+        log::info!("Deleted {} objects under {}", num_deleted, namespace.prefix);
     }
-    log::info!(
-        "Total upload took {:?} seconds",
-        total_start.elapsed().as_secs_f64()
-    );
 }